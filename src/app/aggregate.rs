@@ -1,120 +1,315 @@
-use std::convert::TryFrom;
-
 use prettytable::{Cell, Row, Table};
 
-use crate::shared::{keywords, stringify_document, Config, MongoDbCommand};
+use crate::backend::{Backend, MongoBackend};
+use crate::commands::{Aggregate, AggregateTarget};
+use crate::shared::{
+    parse_document, parse_pipeline_arg, read_pipeline_file, AggregationOptions, Config,
+    OutputFormat, Pipeline, PipelineDescription, Stages,
+};
 
-pub fn aggregate_app() -> clap::App<'static, 'static> {
-    clap::App::new(MongoDbCommand::Aggregate.to_str())
-        .about("Perform aggregation on a collection")
-        .arg(
-            clap::Arg::with_name(keywords::PIPELINE)
-                .long(keywords::PIPELINE)
-                .help("The pipeline to be executed as a string")
-                .required(false)
-                .takes_value(true),
-        )
-        .arg(
-            clap::Arg::with_name("pipeline-name")
-                .long("pipeline-name")
-                .help(
-                    "Name of the pipeline to be called. \
-                    See list",
-                )
-                .required(false)
-                .takes_value(true),
-        )
-        .arg(
-            clap::Arg::with_name(keywords::PIPELINE_INDEX)
-                .long(keywords::PIPELINE_INDEX)
-                .help(
-                    "Index of the pipeline to be called. \
-                    See --list",
-                )
-                .required(false)
-                .takes_value(true),
-        )
-        .arg(
-            clap::Arg::with_name(keywords::LIST)
-                .help(
-                    "List all available pipelines from the configuration file. \
-                    Will be empty if '--config-file' is not passed. \
-                    In this case, please pass the pipeline directly to be executed through '--pipeline'",
-                )
-                .long(keywords::LIST)
-                .required(false),
-        )
+/// Perform aggregation on a collection
+#[derive(clap::Args, Debug)]
+pub struct AggregateArgs {
+    /// The pipeline to be executed as a string
+    #[arg(long, value_parser = parse_pipeline_arg)]
+    pipeline: Option<Stages>,
+    /// A file containing the pipeline to be executed
+    #[arg(long, conflicts_with = "pipeline")]
+    pipeline_file: Option<String>,
+    /// Name of the pipeline to be called. See --list
+    #[arg(long)]
+    pipeline_name: Option<String>,
+    /// Index of the pipeline to be called. See --list
+    #[arg(long)]
+    pipeline_index: Option<usize>,
+    /// Save the pipeline passed through '--pipeline' under this name in the file passed through
+    /// '--config-file' once it ran
+    #[arg(long, requires = "pipeline")]
+    save_as: Option<String>,
+    /// The description of the saved pipeline
+    #[arg(long, requires = "save_as")]
+    description: Option<String>,
+    /// Replace the saved pipeline with the same name
+    #[arg(long, requires = "save_as")]
+    force: bool,
+    #[command(flatten)]
+    options: AggregationArgs,
+    /// Run the pipeline against the database instead of the collection, e.g. for $documents, or
+    /// for $currentOp with '--database-name admin'
+    #[arg(long)]
+    database_level: bool,
+    /// List all available pipelines from the configuration file. Will be empty if
+    /// '--config-file' is not passed. In this case, please pass the pipeline directly to be
+    /// executed through '--pipeline'
+    #[arg(long)]
+    list: bool,
+}
+
+/// The options given on the command line. They override the options of a saved pipeline.
+#[derive(clap::Args, Debug)]
+struct AggregationArgs {
+    /// Let the stages write temporary files when they exceed the memory limit
+    #[arg(long)]
+    allow_disk_use: bool,
+    /// Abort the aggregation after this many milliseconds
+    #[arg(long)]
+    max_time_ms: Option<u64>,
+    /// Number of documents returned per batch by the server
+    #[arg(long)]
+    batch_size: Option<u32>,
+    /// The collation to use, e.g. {"locale": "fr", "strength": 2}
+    #[arg(long, value_parser = parse_document)]
+    collation: Option<mongodb::bson::Document>,
+    /// The index to use, either its name or its key pattern, e.g. {"age": 1}
+    #[arg(long, value_parser = parse_hint)]
+    hint: Option<mongodb::bson::Bson>,
+    /// A comment to find the aggregation in the profiler, currentOp and logs
+    #[arg(long)]
+    comment: Option<String>,
+    /// Variables available to the pipeline as $$<name>, e.g. {"minimum": 10}
+    #[arg(long = "let", value_parser = parse_document)]
+    let_vars: Option<mongodb::bson::Document>,
+}
+
+/// A hint that is not a JSON object is the name of an index.
+fn parse_hint(s: &str) -> Result<mongodb::bson::Bson, std::convert::Infallible> {
+    Ok(match parse_document(s) {
+        Ok(keys) => mongodb::bson::Bson::Document(keys),
+        Err(_) => mongodb::bson::Bson::String(s.to_string()),
+    })
+}
+
+impl AggregationArgs {
+    fn to_options(&self) -> AggregationOptions {
+        AggregationOptions {
+            allow_disk_use: if self.allow_disk_use {
+                Some(true)
+            } else {
+                None
+            },
+            max_time_ms: self.max_time_ms,
+            batch_size: self.batch_size,
+            collation: self.collation.clone(),
+            hint: self.hint.clone(),
+            comment: self.comment.clone(),
+            let_vars: self.let_vars.clone(),
+        }
+    }
+}
+
+/// Print the resulting documents. The cursor of a pipeline writing to a collection is empty,
+/// so report what was written instead.
+fn run_aggregate(
+    backend: &dyn Backend,
+    config: &Config,
+    aggregate: &Aggregate,
+    output_format: OutputFormat,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for result in aggregate.run(backend, &config.database_name)? {
+        writeln!(out, "{}", output_format.format_document(&result?))?;
+    }
+    if let Some((database_name, collection_name)) =
+        aggregate.output_namespace(&config.database_name)
+    {
+        let count = backend.count(&database_name, &collection_name, None)?;
+        writeln!(
+            out,
+            "Wrote the result to {}.{}, which now has {} document{}",
+            database_name,
+            collection_name,
+            count,
+            if count == 1 { "" } else { "s" }
+        )?;
+    }
+    Ok(())
+}
+
+/// The saved pipeline with the options given on the command line applied over its own.
+fn saved_aggregate(
+    config: &Config,
+    pipeline: &Pipeline,
+    database_level: bool,
+    cli_options: AggregationOptions,
+) -> Result<Aggregate, Box<dyn std::error::Error>> {
+    let mut aggregate = Aggregate::from_pipeline(config, pipeline)?;
+    aggregate.options = aggregate.options.merge(cli_options);
+    if database_level {
+        aggregate.target = AggregateTarget::Database;
+    }
+    Ok(aggregate)
 }
 
 pub fn handler(
-    aggregate_matches: &clap::ArgMatches,
+    args: &AggregateArgs,
+    output_format: OutputFormat,
     config: Config,
+    out: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = mongodb::sync::Client::with_uri_str(&config.connection_uri)?;
-    let database = client.database(&config.database_name);
-    let collection =
-        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
-    if aggregate_matches.is_present(keywords::LIST) {
+    let backend = MongoBackend::connect(&config.connection_uri)?;
+    run(args, output_format, &config, &backend, out)
+}
+
+pub fn run(
+    args: &AggregateArgs,
+    output_format: OutputFormat,
+    config: &Config,
+    backend: &dyn Backend,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.list {
         let mut table = Table::new();
         table.add_row(Row::new(vec![
             Cell::new("Index"),
             Cell::new("Name"),
             Cell::new("Description"),
+            Cell::new("Runs on"),
         ]));
         for (idx, p) in config.pipelines.iter().enumerate() {
-            let description = match &p.description {
-                crate::shared::PipelineDescription::OneLine(s) => s.clone(),
-                crate::shared::PipelineDescription::MultiLine(v) => v.join("\n"),
+            let description = p.description.text();
+            let runs_on = if p.database_level {
+                "(database)"
+            } else {
+                p.collection_name
+                    .as_deref()
+                    .unwrap_or(&config.collection_name)
             };
             table.add_row(Row::new(vec![
                 Cell::new(format!("{}", idx).as_str()),
                 Cell::new(p.name.as_str()),
                 Cell::new(description.as_str()),
+                Cell::new(runs_on),
             ]));
         }
-        table.printstd();
-    } else if let Some(pipeline_str) = aggregate_matches.value_of(keywords::PIPELINE) {
-        let value = serde_json::from_str::<serde_json::Value>(pipeline_str)?;
-        match value {
-            serde_json::Value::Array(pipeline) => {
-                let documents = pipeline
-                    .into_iter()
-                    .map(|s| match s {
-                        serde_json::Value::Object(o) => {
-                            mongodb::bson::document::Document::try_from(o)
-                        }
-                        // TODO: Properly handle this error.
-                        _ => panic!("Each stage must be a valid object"),
-                    })
-                    .collect::<Result<Vec<_>, _>>()?;
-                let cursor = collection.aggregate(documents, None)?;
-                for result in cursor {
-                    println!("{}", stringify_document(&result?));
-                }
-            }
-            _ => {
-                return Err("Aggregation pipeline must be an array".into());
-            }
-        };
-    } else if let Some(pipeline_index) = aggregate_matches.value_of(keywords::PIPELINE_INDEX) {
-        let index = usize::from_str_radix(pipeline_index, 10)?;
-        let pipeline_count = config.pipelines.len();
+        table.print(out)?;
+        return Ok(());
+    }
 
-        if let Some(pipeline) = config.pipelines.into_iter().nth(index) {
-            let cursor = collection.aggregate(pipeline.stages, None)?;
-            for result in cursor {
-                println!("{}", stringify_document(&result?));
-            }
-        } else {
-            return Err(format!(
-                "There are only {} pipeline{} available. \
-            Note that it is 0-indexed",
-                pipeline_count,
-                if pipeline_count == 1 { "" } else { "s" },
-            )
-            .into());
-        }
+    let cli_options = args.options.to_options();
+    let target = if args.database_level {
+        AggregateTarget::Database
     } else {
+        AggregateTarget::Collection(config.collection_name.clone())
     };
+    if let Some(documents) = &args.pipeline {
+        let aggregate = Aggregate {
+            stages: config.expand_fragments(documents.clone())?,
+            options: cli_options.clone(),
+            target,
+        };
+        run_aggregate(backend, config, &aggregate, output_format, out)?;
+        if let Some(name) = &args.save_as {
+            let pipeline = Pipeline {
+                name: name.clone(),
+                description: PipelineDescription::from_text(
+                    args.description.as_deref().unwrap_or_default(),
+                ),
+                stages: documents.clone(),
+                stages_file: None,
+                options: cli_options,
+                collection_name: None,
+                database_level: args.database_level,
+            };
+            config.save_pipeline(&pipeline, args.force)?;
+            writeln!(out, "Saved the pipeline as '{}'", name)?;
+        }
+    } else if let Some(pipeline_file) = &args.pipeline_file {
+        let aggregate = Aggregate {
+            stages: config.expand_fragments(read_pipeline_file(pipeline_file)?)?,
+            options: cli_options,
+            target,
+        };
+        run_aggregate(backend, config, &aggregate, output_format, out)?;
+    } else if let Some(pipeline_name) = &args.pipeline_name {
+        let pipeline = config.pipeline_by_name(pipeline_name)?;
+        let aggregate = saved_aggregate(config, pipeline, args.database_level, cli_options)?;
+        run_aggregate(backend, config, &aggregate, output_format, out)?;
+    } else if let Some(index) = args.pipeline_index {
+        let pipeline = config.pipeline_by_index(index)?;
+        let aggregate = saved_aggregate(config, pipeline, args.database_level, cli_options)?;
+        run_aggregate(backend, config, &aggregate, output_format, out)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{run_in_memory, seeded_backend, test_config};
+    use crate::backend::Backend;
+
+    #[test]
+    fn runs_a_pipeline_from_the_command_line() {
+        let output = run_in_memory(
+            &seeded_backend(),
+            &test_config(),
+            &[
+                "--output-format",
+                "json",
+                "aggregate",
+                "--pipeline",
+                r#"[{"$include": "fruit"}, {"$sort": {"_id": -1}}, {"$project": {"_id": 1}}]"#,
+            ],
+        )
+        .unwrap();
+        assert_eq!(output, "{\"_id\":3}\n{\"_id\":1}\n");
+    }
+
+    #[test]
+    fn runs_a_saved_pipeline_by_name_or_index() {
+        let backend = seeded_backend();
+        let config = test_config();
+        for args in [
+            ["aggregate", "--pipeline-name", "fruit-count"],
+            ["aggregate", "--pipeline-index", "0"],
+        ] {
+            let output = run_in_memory(&backend, &config, &args).unwrap();
+            assert_eq!(output, "{ \"fruit\": 2 }\n");
+        }
+    }
+
+    #[test]
+    fn reports_the_saved_pipeline_with_the_result() {
+        let path = std::env::temp_dir().join(format!("magg-save-as-{}.json", std::process::id()));
+        std::fs::write(&path, "{}").unwrap();
+        let mut config = test_config();
+        config.path = Some(path.clone());
+        let output = run_in_memory(
+            &seeded_backend(),
+            &config,
+            &[
+                "aggregate",
+                "--pipeline",
+                r#"[{"$count": "total"}]"#,
+                "--save-as",
+                "total",
+            ],
+        );
+        let saved = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            output.unwrap(),
+            "{ \"total\": 4 }\nSaved the pipeline as 'total'\n"
+        );
+        assert!(saved.unwrap().contains("\"total\""));
+    }
+
+    #[test]
+    fn reports_what_was_written_by_out() {
+        let backend = seeded_backend();
+        let output = run_in_memory(
+            &backend,
+            &test_config(),
+            &[
+                "aggregate",
+                "--pipeline",
+                r#"[{"$match": {"name": "bread"}}, {"$out": "bread"}]"#,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "Wrote the result to test.bread, which now has 1 document\n"
+        );
+        assert_eq!(backend.count("test", "bread", None).unwrap(), 1);
+    }
+}
@@ -0,0 +1,548 @@
+use std::collections::HashSet;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use tui::backend::{Backend, CrosstermBackend};
+use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use tui::{Frame, Terminal};
+
+use crate::shared::{connect, json_to_bson_document, parse_document, stringify_bson, Config};
+
+const PAGE_SIZE: u64 = 20;
+const HELP: &str = "Tab: switch pane  /: filter  Enter: open  Right/Left: expand/collapse  \
+                    n/p: next/previous page  q: quit";
+
+/// Browse the databases, collections and documents in a terminal UI. Read-only
+#[derive(clap::Args, Debug)]
+pub struct BrowseArgs {
+    /// The filter applied when the browser opens
+    #[arg(long, value_parser = parse_document)]
+    input_filter: Option<mongodb::bson::Document>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Namespaces,
+    Documents,
+    Filter,
+    Detail,
+}
+
+/// An entry of the left pane.
+enum Namespace {
+    Database(String),
+    /// A database whose collections could not be listed, e.g. for lack of privileges.
+    Unlisted(String),
+    Collection(String, String),
+}
+
+/// A line of the document pane, either a document or one of its nested fields.
+struct DocumentRow {
+    /// Identifies the row in the set of expanded rows.
+    key: String,
+    /// Index of the document in the current page.
+    document: usize,
+    /// Index of the row this field belongs to.
+    parent: Option<usize>,
+    depth: usize,
+    text: String,
+    expandable: bool,
+}
+
+struct Browser {
+    client: mongodb::sync::Client,
+    namespaces: Vec<Namespace>,
+    namespace_state: ListState,
+    database_name: String,
+    collection_name: String,
+    filter_input: String,
+    filter: mongodb::bson::Document,
+    page: u64,
+    total: u64,
+    documents: Vec<mongodb::bson::Document>,
+    expanded: HashSet<String>,
+    rows: Vec<DocumentRow>,
+    row_state: ListState,
+    detail: Vec<String>,
+    detail_scroll: u16,
+    focus: Focus,
+    status: String,
+}
+
+fn parse_filter(s: &str) -> Result<mongodb::bson::Document, Box<dyn std::error::Error>> {
+    if s.trim().is_empty() {
+        return Ok(mongodb::bson::Document::new());
+    }
+    json_to_bson_document(&serde_json::from_str(s)?, "filter")
+}
+
+fn plural(n: usize, singular: &str) -> String {
+    format!("{} {}{}", n, singular, if n == 1 { "" } else { "s" })
+}
+
+fn summary(value: &mongodb::bson::Bson) -> String {
+    match value {
+        mongodb::bson::Bson::Document(document) => {
+            format!("{{{}}}", plural(document.len(), "field"))
+        }
+        mongodb::bson::Bson::Array(array) => format!("[{}]", plural(array.len(), "item")),
+        o => stringify_bson(o).to_string(),
+    }
+}
+
+fn move_selection(state: &mut ListState, len: usize, delta: isize) {
+    if len == 0 {
+        state.select(None);
+        return;
+    }
+    let selected = state.selected().unwrap_or(0) as isize + delta;
+    state.select(Some(selected.clamp(0, len as isize - 1) as usize));
+}
+
+/// Add a row for each field, followed by the rows of its own fields when it is expanded.
+fn add_field_rows(
+    rows: &mut Vec<DocumentRow>,
+    expanded: &HashSet<String>,
+    document: usize,
+    parent: usize,
+    fields: Vec<(String, &mongodb::bson::Bson)>,
+    depth: usize,
+) {
+    for (name, value) in fields {
+        let key = format!("{}.{}", rows[parent].key, name);
+        let children = match value {
+            mongodb::bson::Bson::Document(d) => {
+                Some(d.iter().map(|(k, v)| (k.clone(), v)).collect::<Vec<_>>())
+            }
+            mongodb::bson::Bson::Array(a) => Some(
+                a.iter()
+                    .enumerate()
+                    .map(|(i, v)| (i.to_string(), v))
+                    .collect::<Vec<_>>(),
+            ),
+            _ => None,
+        };
+        let is_expanded = expanded.contains(&key);
+        let marker = match (&children, is_expanded) {
+            (None, _) => " ",
+            (Some(_), true) => "-",
+            (Some(_), false) => "+",
+        };
+        rows.push(DocumentRow {
+            key,
+            document,
+            parent: Some(parent),
+            depth,
+            text: format!("{} {}: {}", marker, name, summary(value)),
+            expandable: children.is_some(),
+        });
+        if let (Some(children), true) = (children, is_expanded) {
+            let row = rows.len() - 1;
+            add_field_rows(rows, expanded, document, row, children, depth + 1);
+        }
+    }
+}
+
+impl Browser {
+    fn new(client: mongodb::sync::Client, config: &Config) -> Self {
+        Browser {
+            client,
+            namespaces: vec![],
+            namespace_state: ListState::default(),
+            database_name: config.database_name.clone(),
+            collection_name: config.collection_name.clone(),
+            filter_input: String::new(),
+            filter: mongodb::bson::Document::new(),
+            page: 0,
+            total: 0,
+            documents: vec![],
+            expanded: HashSet::new(),
+            rows: vec![],
+            row_state: ListState::default(),
+            detail: vec![],
+            detail_scroll: 0,
+            focus: Focus::Documents,
+            status: HELP.to_string(),
+        }
+    }
+
+    fn load_namespaces(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.namespaces.clear();
+        for database_name in self.client.list_database_names(None, None)? {
+            // One database that cannot be read must not keep the others from being browsed.
+            let mut collection_names = match self
+                .client
+                .database(&database_name)
+                .list_collection_names(None)
+            {
+                Ok(collection_names) => collection_names,
+                Err(_) => {
+                    self.namespaces.push(Namespace::Unlisted(database_name));
+                    continue;
+                }
+            };
+            collection_names.sort();
+            self.namespaces
+                .push(Namespace::Database(database_name.clone()));
+            for collection_name in collection_names {
+                if database_name == self.database_name && collection_name == self.collection_name {
+                    self.namespace_state.select(Some(self.namespaces.len()));
+                }
+                self.namespaces.push(Namespace::Collection(
+                    database_name.clone(),
+                    collection_name,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn load_documents(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let collection = self
+            .client
+            .database(&self.database_name)
+            .collection::<mongodb::bson::Document>(&self.collection_name);
+        self.total = collection.count_documents(self.filter.clone(), None)?;
+        let options = mongodb::options::FindOptions::builder()
+            .skip(self.page * PAGE_SIZE)
+            .limit(PAGE_SIZE as i64)
+            .build();
+        self.documents = collection
+            .find(self.filter.clone(), options)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.expanded.clear();
+        self.build_rows();
+        self.row_state
+            .select(if self.rows.is_empty() { None } else { Some(0) });
+        Ok(())
+    }
+
+    /// Reload the documents, showing failures in the status line instead of leaving the UI.
+    fn reload(&mut self) {
+        self.status = match self.load_documents() {
+            Ok(()) => HELP.to_string(),
+            Err(e) => format!("Error: {}", e),
+        };
+    }
+
+    fn build_rows(&mut self) {
+        let mut rows = vec![];
+        for (index, document) in self.documents.iter().enumerate() {
+            let key = index.to_string();
+            let is_expanded = self.expanded.contains(&key);
+            rows.push(DocumentRow {
+                key,
+                document: index,
+                parent: None,
+                depth: 0,
+                text: format!(
+                    "{} _id: {}  {{{}}}",
+                    if is_expanded { "-" } else { "+" },
+                    document
+                        .get("_id")
+                        .map(|id| stringify_bson(id).to_string())
+                        .unwrap_or_default(),
+                    plural(document.len(), "field")
+                ),
+                expandable: true,
+            });
+            if is_expanded {
+                let row = rows.len() - 1;
+                let fields = document.iter().map(|(k, v)| (k.clone(), v)).collect();
+                add_field_rows(&mut rows, &self.expanded, index, row, fields, 1);
+            }
+        }
+        self.rows = rows;
+    }
+
+    fn set_expanded(&mut self, row: usize, expanded: bool) {
+        let key = self.rows[row].key.clone();
+        if expanded {
+            self.expanded.insert(key.clone());
+        } else {
+            self.expanded.remove(&key);
+        }
+        self.build_rows();
+        let row = self.rows.iter().position(|r| r.key == key);
+        self.row_state.select(row);
+    }
+
+    fn open_detail(&mut self, document: usize) {
+        let json =
+            mongodb::bson::Bson::Document(self.documents[document].clone()).into_relaxed_extjson();
+        self.detail = serde_json::to_string_pretty(&json)
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect();
+        self.detail_scroll = 0;
+        self.focus = Focus::Detail;
+    }
+
+    fn page_count(&self) -> u64 {
+        self.total.div_ceil(PAGE_SIZE).max(1)
+    }
+
+    /// Returns false once the browser should be closed.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+        match self.focus {
+            Focus::Filter => match key.code {
+                KeyCode::Char(c) => self.filter_input.push(c),
+                KeyCode::Backspace => {
+                    self.filter_input.pop();
+                }
+                KeyCode::Enter => match parse_filter(&self.filter_input) {
+                    Ok(filter) => {
+                        self.filter = filter;
+                        self.page = 0;
+                        self.focus = Focus::Documents;
+                        self.reload();
+                    }
+                    Err(e) => self.status = format!("Invalid filter: {}", e),
+                },
+                KeyCode::Esc => self.focus = Focus::Documents,
+                _ => {}
+            },
+            Focus::Detail => match key.code {
+                KeyCode::Esc | KeyCode::Backspace | KeyCode::Char('q') => {
+                    self.focus = Focus::Documents
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.detail_scroll = self.detail_scroll.saturating_sub(1)
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.detail_scroll = self.detail_scroll.saturating_add(1)
+                }
+                KeyCode::PageUp => self.detail_scroll = self.detail_scroll.saturating_sub(10),
+                KeyCode::PageDown => self.detail_scroll = self.detail_scroll.saturating_add(10),
+                _ => {}
+            },
+            Focus::Namespaces | Focus::Documents => match key.code {
+                KeyCode::Char('q') => return false,
+                KeyCode::Tab => {
+                    self.focus = if self.focus == Focus::Namespaces {
+                        Focus::Documents
+                    } else {
+                        Focus::Namespaces
+                    }
+                }
+                KeyCode::Char('/') => self.focus = Focus::Filter,
+                _ if self.focus == Focus::Namespaces => self.handle_namespaces_key(key),
+                _ => self.handle_documents_key(key),
+            },
+        }
+        true
+    }
+
+    fn handle_namespaces_key(&mut self, key: KeyEvent) {
+        let len = self.namespaces.len();
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => move_selection(&mut self.namespace_state, len, -1),
+            KeyCode::Down | KeyCode::Char('j') => move_selection(&mut self.namespace_state, len, 1),
+            KeyCode::Enter => {
+                let namespaces = &self.namespaces;
+                if let Some(Namespace::Collection(database_name, collection_name)) = self
+                    .namespace_state
+                    .selected()
+                    .and_then(|i| namespaces.get(i))
+                {
+                    self.database_name = database_name.clone();
+                    self.collection_name = collection_name.clone();
+                    self.page = 0;
+                    self.focus = Focus::Documents;
+                    self.reload();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_documents_key(&mut self, key: KeyEvent) {
+        let len = self.rows.len();
+        let selected = self.row_state.selected().filter(|i| *i < len);
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => move_selection(&mut self.row_state, len, -1),
+            KeyCode::Down | KeyCode::Char('j') => move_selection(&mut self.row_state, len, 1),
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Char(' ') => {
+                if let Some(row) = selected.filter(|i| self.rows[*i].expandable) {
+                    self.set_expanded(row, true);
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                if let Some(row) = selected {
+                    if self.expanded.contains(&self.rows[row].key) {
+                        self.set_expanded(row, false);
+                    } else if let Some(parent) = self.rows[row].parent {
+                        self.set_expanded(parent, false);
+                    }
+                }
+            }
+            KeyCode::Enter => {
+                if let Some(row) = selected {
+                    self.open_detail(self.rows[row].document);
+                }
+            }
+            KeyCode::Char('n') | KeyCode::PageDown if self.page + 1 < self.page_count() => {
+                self.page += 1;
+                self.reload();
+            }
+            KeyCode::Char('p') | KeyCode::PageUp if self.page > 0 => {
+                self.page -= 1;
+                self.reload();
+            }
+            _ => {}
+        }
+    }
+
+    fn pane(&self, title: String, focus: Focus) -> Block<'static> {
+        let style = if self.focus == focus {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(style)
+            .title(title)
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>) {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(32), Constraint::Min(0)])
+            .split(f.size());
+        let main = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(columns[1]);
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        let namespaces =
+            self.namespaces
+                .iter()
+                .map(|namespace| match namespace {
+                    Namespace::Database(name) => ListItem::new(name.clone())
+                        .style(Style::default().add_modifier(Modifier::BOLD)),
+                    Namespace::Unlisted(name) => {
+                        ListItem::new(format!("{} (collections cannot be listed)", name))
+                            .style(Style::default().fg(Color::DarkGray))
+                    }
+                    Namespace::Collection(_, name) => ListItem::new(format!("  {}", name)),
+                })
+                .collect::<Vec<_>>();
+        let namespaces = List::new(namespaces)
+            .block(self.pane("Namespaces".into(), Focus::Namespaces))
+            .highlight_style(highlight);
+        f.render_stateful_widget(namespaces, columns[0], &mut self.namespace_state);
+
+        let filter = Paragraph::new(self.filter_input.clone())
+            .block(self.pane("Filter".into(), Focus::Filter));
+        f.render_widget(filter, main[0]);
+        if self.focus == Focus::Filter {
+            f.set_cursor(
+                main[0].x + 1 + self.filter_input.chars().count() as u16,
+                main[0].y + 1,
+            );
+        }
+
+        if self.focus == Focus::Detail {
+            let detail = Paragraph::new(self.detail.join("\n"))
+                .block(self.pane("Document (Esc to go back)".into(), Focus::Detail))
+                .scroll((self.detail_scroll, 0));
+            f.render_widget(detail, main[1]);
+        } else {
+            let title = format!(
+                "{}.{}  page {}/{}  {}",
+                self.database_name,
+                self.collection_name,
+                self.page + 1,
+                self.page_count(),
+                plural(self.total as usize, "document")
+            );
+            let rows = self
+                .rows
+                .iter()
+                .map(|row| ListItem::new(format!("{}{}", "  ".repeat(row.depth), row.text)))
+                .collect::<Vec<_>>();
+            let rows = List::new(rows)
+                .block(self.pane(title, Focus::Documents))
+                .highlight_style(highlight);
+            f.render_stateful_widget(rows, main[1], &mut self.row_state);
+        }
+
+        f.render_widget(Paragraph::new(self.status.clone()), main[2]);
+    }
+
+    fn run<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            terminal.draw(|f| self.draw(f))?;
+            if let Event::Key(key) = event::read()? {
+                if !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Raw mode and the alternate screen, left again when dropped so the terminal is restored
+/// even when browsing fails or panics.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self, Box<dyn std::error::Error>> {
+        enable_raw_mode()?;
+        let guard = TerminalGuard;
+        crossterm::execute!(std::io::stdout(), EnterAlternateScreen)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = crossterm::execute!(
+            std::io::stdout(),
+            LeaveAlternateScreen,
+            crossterm::cursor::Show
+        );
+    }
+}
+
+pub fn handler(
+    args: &BrowseArgs,
+    config: Config,
+    _out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !atty::is(atty::Stream::Stdout) || !atty::is(atty::Stream::Stdin) {
+        return Err("The browser needs an interactive terminal".into());
+    }
+    let client = connect(&config.connection_uri)?;
+    let mut browser = Browser::new(client, &config);
+    if let Some(filter) = &args.input_filter {
+        browser.filter = filter.clone();
+        browser.filter_input = mongodb::bson::Bson::Document(filter.clone())
+            .into_relaxed_extjson()
+            .to_string();
+    }
+    browser.load_namespaces()?;
+    browser.reload();
+
+    let _guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+    browser.run(&mut terminal)
+}
@@ -0,0 +1,463 @@
+use std::io::BufRead;
+
+use serde::Deserialize;
+
+use crate::shared::{
+    bson_as_i64, connect, convert_json_to_bson, json_to_bson_document, read_json_lines, Config,
+    InputType,
+};
+
+/// Execute a list of write operations in batches
+#[derive(clap::Args, Debug)]
+pub struct BulkWriteArgs {
+    /// Get the operations directly as an argument. Expects JSON lines with one operation per
+    /// line, e.g. {"insertOne": {"document": {...}}}
+    #[arg(long)]
+    input_operations: Option<String>,
+    /// Get the operations from a file. Expects JSON lines
+    #[arg(long)]
+    input_file: Option<String>,
+    /// Whether to stop at the first failed operation. Unordered writes continue with the
+    /// remaining operations
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    ordered: bool,
+    /// Maximum number of operations sent to the server in a single command
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+}
+
+/// A single line of the operations file.
+/// Mirrors the format accepted by the shell's `bulkWrite`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+enum WriteModel {
+    InsertOne {
+        document: serde_json::Value,
+    },
+    UpdateOne {
+        filter: serde_json::Value,
+        update: serde_json::Value,
+        #[serde(default)]
+        upsert: bool,
+    },
+    UpdateMany {
+        filter: serde_json::Value,
+        update: serde_json::Value,
+        #[serde(default)]
+        upsert: bool,
+    },
+    ReplaceOne {
+        filter: serde_json::Value,
+        replacement: serde_json::Value,
+        #[serde(default)]
+        upsert: bool,
+    },
+    DeleteOne {
+        filter: serde_json::Value,
+    },
+    DeleteMany {
+        filter: serde_json::Value,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum WriteKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl WriteKind {
+    fn command_name(self) -> &'static str {
+        match self {
+            WriteKind::Insert => "insert",
+            WriteKind::Update => "update",
+            WriteKind::Delete => "delete",
+        }
+    }
+
+    fn statements_field(self) -> &'static str {
+        match self {
+            WriteKind::Insert => "documents",
+            WriteKind::Update => "updates",
+            WriteKind::Delete => "deletes",
+        }
+    }
+}
+
+/// Convert an operation into the statement expected by the corresponding write command.
+fn to_statement(
+    model: WriteModel,
+) -> Result<(WriteKind, mongodb::bson::Document), Box<dyn std::error::Error>> {
+    let statement = match model {
+        WriteModel::InsertOne { document } => (
+            WriteKind::Insert,
+            json_to_bson_document(&document, "document")?,
+        ),
+        WriteModel::UpdateOne {
+            filter,
+            update,
+            upsert,
+        } => (
+            WriteKind::Update,
+            mongodb::bson::doc! {
+                "q": json_to_bson_document(&filter, "filter")?,
+                "u": convert_json_to_bson(&update),
+                "upsert": upsert,
+                "multi": false,
+            },
+        ),
+        WriteModel::UpdateMany {
+            filter,
+            update,
+            upsert,
+        } => (
+            WriteKind::Update,
+            mongodb::bson::doc! {
+                "q": json_to_bson_document(&filter, "filter")?,
+                "u": convert_json_to_bson(&update),
+                "upsert": upsert,
+                "multi": true,
+            },
+        ),
+        WriteModel::ReplaceOne {
+            filter,
+            replacement,
+            upsert,
+        } => (
+            WriteKind::Update,
+            mongodb::bson::doc! {
+                "q": json_to_bson_document(&filter, "filter")?,
+                "u": json_to_bson_document(&replacement, "replacement")?,
+                "upsert": upsert,
+                "multi": false,
+            },
+        ),
+        WriteModel::DeleteOne { filter } => (
+            WriteKind::Delete,
+            mongodb::bson::doc! {
+                "q": json_to_bson_document(&filter, "filter")?,
+                "limit": 1,
+            },
+        ),
+        WriteModel::DeleteMany { filter } => (
+            WriteKind::Delete,
+            mongodb::bson::doc! {
+                "q": json_to_bson_document(&filter, "filter")?,
+                "limit": 0,
+            },
+        ),
+    };
+    Ok(statement)
+}
+
+/// Consecutive operations of the same kind that are sent as one write command.
+/// Each statement remembers the line it came from so errors can be reported against it.
+struct Batch {
+    kind: WriteKind,
+    statements: Vec<(usize, mongodb::bson::Document)>,
+}
+
+impl Batch {
+    fn command(&self, collection_name: &str, ordered: bool) -> mongodb::bson::Document {
+        let mut command = mongodb::bson::Document::new();
+        command.insert(self.kind.command_name(), collection_name);
+        command.insert(
+            self.kind.statements_field(),
+            self.statements
+                .iter()
+                .map(|(_, s)| mongodb::bson::Bson::Document(s.clone()))
+                .collect::<Vec<_>>(),
+        );
+        command.insert("ordered", ordered);
+        command
+    }
+
+    fn first_line(&self) -> usize {
+        self.statements.first().map(|(line, _)| *line).unwrap_or(0)
+    }
+
+    fn last_line(&self) -> usize {
+        self.statements.last().map(|(line, _)| *line).unwrap_or(0)
+    }
+}
+
+fn read_batches<R>(reader: R, batch_size: usize) -> Result<Vec<Batch>, Box<dyn std::error::Error>>
+where
+    R: BufRead,
+{
+    let mut batches: Vec<Batch> = Vec::new();
+    for (line_number, model) in read_json_lines::<WriteModel, _>(reader)? {
+        let (kind, statement) =
+            to_statement(model).map_err(|e| format!("Line {}: {}", line_number, e))?;
+        match batches.last_mut() {
+            Some(batch) if batch.kind == kind && batch.statements.len() < batch_size => {
+                batch.statements.push((line_number, statement))
+            }
+            _ => batches.push(Batch {
+                kind,
+                statements: vec![(line_number, statement)],
+            }),
+        }
+    }
+    Ok(batches)
+}
+
+#[derive(Default, Debug)]
+struct BulkWriteSummary {
+    inserted: i64,
+    matched: i64,
+    modified: i64,
+    deleted: i64,
+    upserted: i64,
+    errors: Vec<(usize, String)>,
+}
+
+impl BulkWriteSummary {
+    fn print(&self, out: &mut dyn std::io::Write) -> std::io::Result<()> {
+        writeln!(out, "Inserted: {}", self.inserted)?;
+        writeln!(out, "Matched: {}", self.matched)?;
+        writeln!(out, "Modified: {}", self.modified)?;
+        writeln!(out, "Deleted: {}", self.deleted)?;
+        writeln!(out, "Upserted: {}", self.upserted)?;
+        if !self.errors.is_empty() {
+            writeln!(out, "Errors:")?;
+            for (line, error) in self.errors.iter() {
+                writeln!(out, "Line {}: {}", line, error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Count the reply of a batch. Returns whether any of its operations failed.
+    fn add_reply(&mut self, batch: &Batch, reply: &mongodb::bson::Document) -> bool {
+        let n = reply.get("n").and_then(bson_as_i64).unwrap_or(0);
+        match batch.kind {
+            WriteKind::Insert => self.inserted += n,
+            WriteKind::Delete => self.deleted += n,
+            WriteKind::Update => {
+                let upserted = reply.get_array("upserted").map(|u| u.len()).unwrap_or(0) as i64;
+                self.upserted += upserted;
+                self.matched += n - upserted;
+                self.modified += reply.get("nModified").and_then(bson_as_i64).unwrap_or(0);
+            }
+        }
+
+        let write_errors = reply.get_array("writeErrors").cloned().unwrap_or_default();
+        for error in write_errors.iter().filter_map(|e| e.as_document()) {
+            let index = error.get("index").and_then(bson_as_i64).unwrap_or(0) as usize;
+            let line_number = batch
+                .statements
+                .get(index)
+                .map(|(line, _)| *line)
+                .unwrap_or(0);
+            self.errors.push((
+                line_number,
+                format!(
+                    "{} (code {})",
+                    error.get_str("errmsg").unwrap_or("unknown error"),
+                    error.get("code").and_then(bson_as_i64).unwrap_or(0)
+                ),
+            ));
+        }
+        if let Ok(error) = reply.get_document("writeConcernError") {
+            self.errors.push((
+                batch.last_line(),
+                format!(
+                    "Write concern error: {}",
+                    error.get_str("errmsg").unwrap_or("unknown error")
+                ),
+            ));
+        }
+        !write_errors.is_empty()
+    }
+}
+
+/// Send the batches one after the other through `run_command`. Ordered writes stop after the
+/// first batch with a failed operation.
+fn write_batches<F>(
+    batches: Vec<Batch>,
+    collection_name: &str,
+    ordered: bool,
+    mut run_command: F,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnMut(
+        mongodb::bson::Document,
+    ) -> Result<mongodb::bson::Document, Box<dyn std::error::Error>>,
+{
+    let mut summary = BulkWriteSummary::default();
+    let batch_count = batches.len();
+    for (batch_index, batch) in batches.iter().enumerate() {
+        let reply = match run_command(batch.command(collection_name, ordered)) {
+            Ok(reply) => reply,
+            // Earlier batches may have been written already, so report them before failing.
+            Err(e) => {
+                summary.print(out)?;
+                writeln!(
+                    out,
+                    "Batch {} of {} (lines {}-{}) failed: {}",
+                    batch_index + 1,
+                    batch_count,
+                    batch.first_line(),
+                    batch.last_line(),
+                    e
+                )?;
+                return Err(e);
+            }
+        };
+        if summary.add_reply(batch, &reply) && ordered {
+            break;
+        }
+    }
+
+    summary.print(out)?;
+    if !summary.errors.is_empty() {
+        return Err(format!(
+            "{} operation{} failed",
+            summary.errors.len(),
+            if summary.errors.len() == 1 { "" } else { "s" }
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn handler(
+    args: &BulkWriteArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let database = client.database(&config.database_name);
+    let handle = InputType::from_args(
+        args.input_file.as_deref(),
+        args.input_operations.as_deref(),
+        "input-operations",
+    )?;
+    let batches = read_batches(handle.into_reader(), args.batch_size as usize)?;
+    write_batches(
+        batches,
+        &config.collection_name,
+        args.ordered,
+        |command| Ok(database.run_command(command, None)?),
+        out,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    const OPERATIONS: &str = r#"{"insertOne": {"document": {"_id": 1}}}
+{"insertOne": {"document": {"_id": 2}}}
+
+{"insertOne": {"document": {"_id": 3}}}
+{"deleteOne": {"filter": {"name": "apple"}}}
+{"insertOne": {"document": {"_id": 4}}}
+"#;
+
+    fn batches() -> Vec<Batch> {
+        read_batches(OPERATIONS.as_bytes(), 2).unwrap()
+    }
+
+    /// Write the batches with the given replies, returning the output and the commands sent.
+    fn write(
+        ordered: bool,
+        mut replies: Vec<Result<mongodb::bson::Document, Box<dyn std::error::Error>>>,
+    ) -> (
+        String,
+        Vec<mongodb::bson::Document>,
+        Result<(), Box<dyn std::error::Error>>,
+    ) {
+        replies.reverse();
+        let mut commands = vec![];
+        let mut out = vec![];
+        let result = write_batches(
+            batches(),
+            "items",
+            ordered,
+            |command| {
+                commands.push(command);
+                replies.pop().unwrap()
+            },
+            &mut out,
+        );
+        (String::from_utf8(out).unwrap(), commands, result)
+    }
+
+    #[test]
+    fn splits_the_operations_by_kind_and_batch_size() {
+        let batches = batches();
+        assert_eq!(
+            batches
+                .iter()
+                .map(|b| (b.kind, b.statements.iter().map(|(line, _)| *line).collect()))
+                .collect::<Vec<(WriteKind, Vec<usize>)>>(),
+            vec![
+                (WriteKind::Insert, vec![1, 2]),
+                (WriteKind::Insert, vec![4]),
+                (WriteKind::Delete, vec![5]),
+                (WriteKind::Insert, vec![6]),
+            ]
+        );
+        assert_eq!(
+            batches[2].command("items", false),
+            doc! {
+                "delete": "items",
+                "deletes": [{ "q": { "name": "apple" }, "limit": 1 }],
+                "ordered": false,
+            }
+        );
+    }
+
+    fn duplicate_key_reply() -> mongodb::bson::Document {
+        doc! {
+            "n": 1,
+            "writeErrors": [{ "index": 1, "code": 11000, "errmsg": "duplicate key" }],
+        }
+    }
+
+    #[test]
+    fn stops_after_a_failed_operation_when_ordered() {
+        let (output, commands, result) = write(true, vec![Ok(duplicate_key_reply())]);
+        assert_eq!(commands.len(), 1);
+        assert!(output.contains("Inserted: 1\n"));
+        assert!(output.ends_with("Errors:\nLine 2: duplicate key (code 11000)\n"));
+        assert_eq!(result.unwrap_err().to_string(), "1 operation failed");
+    }
+
+    #[test]
+    fn continues_after_a_failed_operation_when_unordered() {
+        let (output, commands, result) = write(
+            false,
+            vec![
+                Ok(duplicate_key_reply()),
+                Ok(doc! { "n": 1 }),
+                Ok(doc! { "n": 1 }),
+                Ok(doc! { "n": 1 }),
+            ],
+        );
+        assert_eq!(commands.len(), 4);
+        assert!(output.starts_with("Inserted: 3\nMatched: 0\nModified: 0\nDeleted: 1\n"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn reports_the_written_batches_when_a_command_fails() {
+        let (output, commands, result) = write(
+            true,
+            vec![Ok(doc! { "n": 2 }), Err("connection closed".into())],
+        );
+        assert_eq!(commands.len(), 2);
+        assert_eq!(
+            output,
+            "Inserted: 2\nMatched: 0\nModified: 0\nDeleted: 0\nUpserted: 0\n\
+            Batch 2 of 4 (lines 4-4) failed: connection closed\n"
+        );
+        assert_eq!(result.unwrap_err().to_string(), "connection closed");
+    }
+}
@@ -0,0 +1,299 @@
+use clap::ValueEnum;
+use clap_complete::Shell;
+
+use crate::shared::{Config, GlobalArgs};
+
+/// The options whose values are completed by running `magg complete <kind>`.
+const DYNAMIC_OPTIONS: &[(&str, Kind)] = &[
+    ("pipeline-name", Kind::PipelineNames),
+    ("pipeline-index", Kind::PipelineIndexes),
+    ("database-name", Kind::Databases),
+    ("target-database-name", Kind::Databases),
+    ("collection-name", Kind::Collections),
+    ("target-collection-name", Kind::Collections),
+];
+
+/// How long completing the database and collection names waits for the server.
+const SERVER_SELECTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Print the completion script of the shell. In bash, zsh and fish the saved pipelines and the
+/// database and collection names are completed too, using the --config-file and
+/// --connection-uri already typed, e.g. 'magg completions bash > /etc/bash_completion.d/magg'
+#[derive(clap::Args, Debug)]
+pub struct CompletionsArgs {
+    /// The shell the script is for
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+/// Print the values completing an option, one per line. Used by the completion scripts
+#[derive(clap::Args, Debug)]
+pub struct CompleteArgs {
+    /// What is completed
+    #[arg(value_enum)]
+    kind: Kind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Kind {
+    PipelineNames,
+    PipelineIndexes,
+    Databases,
+    Collections,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => write!(f, "{}", value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
+
+const BASH_VALUES: &str = r#"_magg_values() {
+    local i args=()
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${COMP_WORDS[i]}" in
+            --config-file|--connection-uri|--database-name)
+                args+=("${COMP_WORDS[i]}" "${COMP_WORDS[i+1]}")
+                ;;
+        esac
+    done
+    magg "${args[@]}" complete "$1" 2>/dev/null
+}
+
+"#;
+
+// The words of the subcommand replace $words, so the options before it are read from the line.
+const ZSH_VALUES: &str = r#"
+_magg_values() {
+    local i
+    local -a line_words args values
+    line_words=(${(z)BUFFER})
+    for ((i = 2; i < ${#line_words}; i++)); do
+        case "${line_words[i]}" in
+            --config-file|--connection-uri|--database-name)
+                args+=("${line_words[i]}" "${(Q)line_words[i+1]}")
+                ;;
+        esac
+    done
+    values=(${(f)"$(magg "${args[@]}" complete "$1" 2>/dev/null)"})
+    compadd -a values
+}
+"#;
+
+const FISH_VALUES: &str = r#"function __magg_values
+    set -l tokens (commandline -opc)
+    set -l args
+    for i in (seq 2 (math (count $tokens) - 1))
+        switch $tokens[$i]
+            case --config-file --connection-uri --database-name
+                set -a args $tokens[$i] $tokens[(math $i + 1)]
+        end
+    end
+    magg $args complete $argv[1] 2>/dev/null
+end
+
+"#;
+
+/// Make the options of `DYNAMIC_OPTIONS` complete their values with `_magg_values <kind>` in
+/// the script generated by clap.
+fn add_dynamic_values(shell: Shell, script: &str) -> String {
+    let kind_of = |option: &str| {
+        DYNAMIC_OPTIONS
+            .iter()
+            .find(|(name, _)| *name == option)
+            .map(|(_, kind)| *kind)
+    };
+    let mut lines = Vec::new();
+    // bash: the line following "--pipeline-name)" completes file names.
+    let mut bash_kind = None;
+    for line in script.lines() {
+        let trimmed = line.trim_start();
+        match shell {
+            Shell::Bash => {
+                if let Some(kind) = bash_kind.take() {
+                    if trimmed.starts_with("COMPREPLY=($(compgen -f") {
+                        let indent = &line[..line.len() - trimmed.len()];
+                        lines.push(format!(
+                            "{}COMPREPLY=($(compgen -W \"$(_magg_values {})\" -- \"${{cur}}\"))",
+                            indent, kind
+                        ));
+                        continue;
+                    }
+                }
+                bash_kind = trimmed
+                    .strip_prefix("--")
+                    .and_then(|option| option.strip_suffix(')'))
+                    .and_then(kind_of);
+            }
+            // e.g. '--pipeline-name=[Name of the pipeline]:PIPELINE_NAME:_default' \
+            Shell::Zsh => {
+                let option = trimmed
+                    .split_once("=[")
+                    .and_then(|(option, _)| option.rsplit("--").next())
+                    .filter(|_| trimmed.starts_with('\'') && trimmed.ends_with(":_default' \\"));
+                // The help may contain colons, the value name and action never do.
+                let described = line.rsplitn(3, ':').nth(2);
+                if let (Some(kind), Some(described)) = (option.and_then(kind_of), described) {
+                    lines.push(format!("{}:{}:_magg_values {}' \\", described, kind, kind));
+                    continue;
+                }
+            }
+            // e.g. complete -c magg -n "__fish_magg_using_subcommand aggregate" -l pipeline-name
+            // -d 'Name of the pipeline' -r
+            Shell::Fish => {
+                let option = trimmed
+                    .split(" -l ")
+                    .nth(1)
+                    .and_then(|rest| rest.split(' ').next());
+                if let Some(kind) = option.and_then(kind_of) {
+                    lines.push(format!("{} -f -a \"(__magg_values {})\"", line, kind));
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        lines.push(line.to_string());
+    }
+    let script = lines.join("\n") + "\n";
+    match shell {
+        Shell::Bash => format!("{}{}", BASH_VALUES, script),
+        Shell::Zsh => format!("{}{}", script, ZSH_VALUES),
+        Shell::Fish => format!("{}{}", FISH_VALUES, script),
+        _ => script,
+    }
+}
+
+pub fn handler(
+    args: &CompletionsArgs,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut script = Vec::new();
+    clap_complete::generate(
+        args.shell,
+        &mut super::main_app(),
+        clap::crate_name!(),
+        &mut script,
+    );
+    write!(
+        out,
+        "{}",
+        add_dynamic_values(args.shell, &String::from_utf8(script)?)
+    )?;
+    Ok(())
+}
+
+fn values(kind: Kind, args: &GlobalArgs) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let config = args
+        .config_file
+        .as_ref()
+        .map(Config::from_file)
+        .transpose()?;
+    match kind {
+        Kind::PipelineNames => Ok(config
+            .map(|config| config.pipelines.into_iter().map(|p| p.name).collect())
+            .unwrap_or_default()),
+        Kind::PipelineIndexes => Ok(
+            (0..config.map(|config| config.pipelines.len()).unwrap_or(0))
+                .map(|index| index.to_string())
+                .collect(),
+        ),
+        Kind::Databases | Kind::Collections => {
+            let connection_uri = args
+                .connection_uri
+                .clone()
+                .or_else(|| config.as_ref().map(|c| c.connection_uri.clone()))
+                .ok_or("Please provide the connection-uri")?;
+            // Completing must not keep the shell waiting for an unreachable server.
+            let mut options = mongodb::options::ClientOptions::parse(&connection_uri)?;
+            options.server_selection_timeout = Some(SERVER_SELECTION_TIMEOUT);
+            let client = mongodb::sync::Client::with_options(options)?;
+            if kind == Kind::Databases {
+                return Ok(client.list_database_names(None, None)?);
+            }
+            let database_name = args
+                .database_name
+                .clone()
+                .or_else(|| config.map(|c| c.database_name))
+                .ok_or("Please provide the database-name")?;
+            let mut names = client
+                .database(&database_name)
+                .list_collection_names(None)?;
+            names.sort();
+            Ok(names)
+        }
+    }
+}
+
+/// `global` are the arguments of the main command, which has the connection options.
+pub fn complete_handler(
+    args: &CompleteArgs,
+    global: &GlobalArgs,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for value in values(args.kind, global)? {
+        writeln!(out, "{}", value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completes_the_saved_pipelines_in_bash() {
+        let script = add_dynamic_values(
+            Shell::Bash,
+            "            case \"${prev}\" in\n\
+            \x20               --pipeline-name)\n\
+            \x20                   COMPREPLY=($(compgen -f \"${cur}\"))\n\
+            \x20                   return 0\n\
+            \x20                   ;;\n\
+            \x20               --save-as)\n\
+            \x20                   COMPREPLY=($(compgen -f \"${cur}\"))\n",
+        );
+        assert!(script.starts_with(BASH_VALUES));
+        assert!(script.ends_with(
+            "                --pipeline-name)\n\
+            \x20                   COMPREPLY=($(compgen -W \"$(_magg_values pipeline-names)\" -- \"${cur}\"))\n\
+            \x20                   return 0\n\
+            \x20                   ;;\n\
+            \x20               --save-as)\n\
+            \x20                   COMPREPLY=($(compgen -f \"${cur}\"))\n"
+        ));
+    }
+
+    #[test]
+    fn completes_the_names_in_zsh_and_fish() {
+        assert_eq!(
+            add_dynamic_values(
+                Shell::Zsh,
+                "'--target-database-name=[The database of the target]\
+                :TARGET_DATABASE_NAME:_default' \\\n\
+                '--save-as=[Save the pipeline]:SAVE_AS:_default' \\\n"
+            ),
+            format!(
+                "'--target-database-name=[The database of the target]\
+                :databases:_magg_values databases' \\\n\
+                '--save-as=[Save the pipeline]:SAVE_AS:_default' \\\n{}",
+                ZSH_VALUES
+            )
+        );
+        assert_eq!(
+            add_dynamic_values(
+                Shell::Fish,
+                "complete -c magg -n \"__fish_magg_needs_command\" -l collection-name -r\n\
+                complete -c magg -n \"__fish_magg_needs_command\" -l config-file -r\n"
+            ),
+            format!(
+                "{}complete -c magg -n \"__fish_magg_needs_command\" -l collection-name -r \
+                -f -a \"(__magg_values collections)\"\n\
+                complete -c magg -n \"__fish_magg_needs_command\" -l config-file -r\n",
+                FISH_VALUES
+            )
+        );
+    }
+}
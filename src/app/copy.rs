@@ -0,0 +1,365 @@
+use std::io::Write;
+
+use crate::backend::{Backend, MongoBackend};
+use crate::commands::{Aggregate, AggregateTarget};
+use crate::shared::{
+    bson_as_i64, connect, create_index_specs, get_path, list_index_specs, parse_document,
+    parse_pipeline_arg, Config, GlobalArgs, Stages,
+};
+
+/// Copy the documents of the collection into another collection
+#[derive(clap::Args, Debug)]
+pub struct CopyArgs {
+    /// Only copy the documents that match this filter
+    #[arg(long, value_parser = parse_document)]
+    input_filter: Option<mongodb::bson::Document>,
+    /// Copy the result of this pipeline instead of the documents
+    #[arg(long, value_parser = parse_pipeline_arg, conflicts_with = "input_filter")]
+    pipeline: Option<Stages>,
+    /// Copy the result of the saved pipeline with this name
+    #[arg(long, conflicts_with_all = ["input_filter", "pipeline"])]
+    pipeline_name: Option<String>,
+    /// Copy the result of the saved pipeline at this index
+    #[arg(long, conflicts_with_all = ["input_filter", "pipeline", "pipeline_name"])]
+    pipeline_index: Option<usize>,
+    /// A configuration file describing the target. Defaults to the source connection, database
+    /// and collection
+    #[arg(long)]
+    target_config_file: Option<String>,
+    /// The connection URI of the target
+    #[arg(long)]
+    target_connection_uri: Option<String>,
+    /// The database of the target
+    #[arg(long)]
+    target_database_name: Option<String>,
+    /// The collection of the target
+    #[arg(long)]
+    target_collection_name: Option<String>,
+    /// Replace the target documents with the same value for this field, e.g. '_id' or the dotted
+    /// path 'meta.id', instead of inserting
+    #[arg(long)]
+    upsert_by: Option<String>,
+    /// Drop the target collection before copying
+    #[arg(long)]
+    drop_target: bool,
+    /// Create the indexes of the source collection on the target
+    #[arg(long)]
+    copy_indexes: bool,
+    /// Number of documents written per command
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+}
+
+/// The target defaults to the source and is resolved like the global arguments: the target
+/// configuration file takes precedence over the target connection URI, and the target database
+/// and collection override either.
+fn target_config(args: &CopyArgs, source: &Config) -> Result<Config, Box<dyn std::error::Error>> {
+    let (database_name, collection_name) = match &args.target_config_file {
+        Some(_) => (
+            args.target_database_name.clone(),
+            args.target_collection_name.clone(),
+        ),
+        None => (
+            args.target_database_name
+                .clone()
+                .or_else(|| Some(source.database_name.clone())),
+            args.target_collection_name
+                .clone()
+                .or_else(|| Some(source.collection_name.clone())),
+        ),
+    };
+    Config::new(
+        &source.connection_uri,
+        &source.database_name,
+        &source.collection_name,
+    )
+    .with_overrides(&GlobalArgs {
+        connection_uri: args.target_connection_uri.clone(),
+        database_name,
+        collection_name,
+        config_file: args.target_config_file.clone(),
+        ..Default::default()
+    })
+}
+
+/// What is copied: the result of a pipeline, or the documents that match the filter.
+fn source_aggregate(
+    args: &CopyArgs,
+    config: &Config,
+) -> Result<Option<Aggregate>, Box<dyn std::error::Error>> {
+    let aggregate = if let Some(pipeline) = &args.pipeline {
+        Aggregate {
+            stages: config.expand_fragments(pipeline.clone())?,
+            options: Default::default(),
+            target: AggregateTarget::Collection(config.collection_name.clone()),
+        }
+    } else if let Some(name) = &args.pipeline_name {
+        Aggregate::from_pipeline(config, config.pipeline_by_name(name)?)?
+    } else if let Some(index) = args.pipeline_index {
+        Aggregate::from_pipeline(config, config.pipeline_by_index(index)?)?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(aggregate))
+}
+
+/// The update statement replacing the target document that has the same value at `field`, which
+/// may be a dotted path into embedded documents.
+fn upsert_statement(
+    document: mongodb::bson::Document,
+    field: &str,
+) -> Result<mongodb::bson::Document, Box<dyn std::error::Error>> {
+    let key = get_path(&document, field)
+        .cloned()
+        .ok_or_else(|| format!("Document is missing the '{}' field", field))?;
+    let mut filter = mongodb::bson::Document::new();
+    filter.insert(field, key);
+    Ok(mongodb::bson::doc! {
+        "q": filter,
+        "u": document,
+        "upsert": true,
+    })
+}
+
+fn write_batch(
+    database: &mongodb::sync::Database,
+    collection: &mongodb::sync::Collection<mongodb::bson::Document>,
+    batch: Vec<mongodb::bson::Document>,
+    upsert_by: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match upsert_by {
+        None => {
+            collection.insert_many(batch, None)?;
+        }
+        Some(field) => {
+            let updates = batch
+                .into_iter()
+                .map(|document| {
+                    upsert_statement(document, field).map(mongodb::bson::Bson::Document)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let reply = database.run_command(
+                mongodb::bson::doc! {
+                    "update": collection.name(),
+                    "updates": updates,
+                    "ordered": true,
+                },
+                None,
+            )?;
+            if let Some(error) = reply
+                .get_array("writeErrors")
+                .ok()
+                .and_then(|e| e.first())
+                .and_then(|e| e.as_document())
+            {
+                return Err(format!(
+                    "{} (code {})",
+                    error.get_str("errmsg").unwrap_or("unknown error"),
+                    error.get("code").and_then(bson_as_i64).unwrap_or(0)
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn handler(
+    args: &CopyArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let target = target_config(args, &config)?;
+    let aggregate = source_aggregate(args, &config)?;
+    // A saved pipeline may run against its own collection or against the database.
+    let source_name = match aggregate.as_ref().map(|a| &a.target) {
+        Some(AggregateTarget::Database) => None,
+        Some(AggregateTarget::Collection(collection_name)) => Some(collection_name.as_str()),
+        None => Some(config.collection_name.as_str()),
+    };
+    if target.connection_uri == config.connection_uri
+        && target.database_name == config.database_name
+        && source_name == Some(target.collection_name.as_str())
+    {
+        return Err("The target must be different from the source".into());
+    }
+    let source = MongoBackend::connect(&config.connection_uri)?;
+    let source_database = source.client().database(&config.database_name);
+    let target_client = connect(&target.connection_uri)?;
+    let target_database = target_client.database(&target.database_name);
+    let target_collection =
+        target_database.collection::<mongodb::bson::document::Document>(&target.collection_name);
+    let upsert_by = args.upsert_by.as_deref();
+    let batch_size = args.batch_size as usize;
+
+    let (cursor, total) = match &aggregate {
+        Some(aggregate) => (aggregate.run(&source, &config.database_name)?, None),
+        None => {
+            let copy_filter = args.input_filter.clone();
+            let total = source.count(
+                &config.database_name,
+                &config.collection_name,
+                copy_filter.clone(),
+            )?;
+            (
+                source.find(
+                    &config.database_name,
+                    &config.collection_name,
+                    copy_filter,
+                    Default::default(),
+                )?,
+                Some(total),
+            )
+        }
+    };
+
+    if args.drop_target {
+        target_collection.drop(None)?;
+    }
+    if args.copy_indexes {
+        let source_name = source_name.ok_or("Indexes can only be copied from a collection")?;
+        let indexes = list_index_specs(&source_database, source_name)?;
+        let count = create_index_specs(&target_database, &target.collection_name, &indexes)?;
+        eprintln!(
+            "Copied {} index{}",
+            count,
+            if count == 1 { "" } else { "es" }
+        );
+    }
+
+    let report_progress = |copied: usize| {
+        match total {
+            Some(total) => eprint!("\rCopied {}/{} documents", copied, total),
+            None => eprint!("\rCopied {} documents", copied),
+        }
+        let _ = std::io::stderr().flush();
+    };
+    let mut copied = 0;
+    let mut batch = Vec::with_capacity(batch_size);
+    for result in cursor {
+        batch.push(result?);
+        if batch.len() == batch_size {
+            copied += batch.len();
+            write_batch(
+                &target_database,
+                &target_collection,
+                std::mem::replace(&mut batch, Vec::with_capacity(batch_size)),
+                upsert_by,
+            )?;
+            report_progress(copied);
+        }
+    }
+    if !batch.is_empty() {
+        copied += batch.len();
+        write_batch(&target_database, &target_collection, batch, upsert_by)?;
+        report_progress(copied);
+    }
+    if copied > 0 {
+        eprintln!();
+    }
+    writeln!(
+        out,
+        "Copied {} document{} from {} to {}.{}",
+        copied,
+        if copied == 1 { "" } else { "s" },
+        match source_name {
+            Some(source_name) => format!("{}.{}", config.database_name, source_name),
+            None => config.database_name.clone(),
+        },
+        target.database_name,
+        target.collection_name
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use mongodb::bson::doc;
+
+    use super::*;
+    use crate::app::{Command, Magg};
+    use crate::shared::{Pipeline, PipelineDescription};
+
+    fn copy_args(args: &[&str]) -> CopyArgs {
+        let magg = Magg::try_parse_from(["magg", "copy"].iter().chain(args)).unwrap();
+        match magg.command {
+            Some(Command::Copy(args)) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    fn pipeline(name: &str, collection_name: Option<&str>, database_level: bool) -> Pipeline {
+        Pipeline {
+            name: name.to_string(),
+            description: PipelineDescription::OneLine(String::new()),
+            stages: vec![doc! { "$match": {} }],
+            stages_file: None,
+            options: Default::default(),
+            collection_name: collection_name.map(|c| c.to_string()),
+            database_level,
+        }
+    }
+
+    #[test]
+    fn defaults_the_target_to_the_source() {
+        let source = Config::new("mongodb://source", "test", "items");
+        let target =
+            target_config(&copy_args(&["--target-collection-name", "copied"]), &source).unwrap();
+        assert_eq!(target.connection_uri, "mongodb://source");
+        assert_eq!(target.database_name, "test");
+        assert_eq!(target.collection_name, "copied");
+
+        let target = target_config(
+            &copy_args(&["--target-connection-uri", "mongodb://target"]),
+            &source,
+        )
+        .unwrap();
+        assert_eq!(target.connection_uri, "mongodb://target");
+        assert_eq!(target.database_name, "test");
+        assert_eq!(target.collection_name, "items");
+    }
+
+    #[test]
+    fn runs_saved_pipelines_against_their_own_target() {
+        let mut config = Config::new("mongodb://localhost", "test", "items");
+        config
+            .pipelines
+            .push(pipeline("orders", Some("orders"), false));
+        config.pipelines.push(pipeline("operations", None, true));
+        config.pipelines.push(pipeline("items", None, false));
+        let target = |args: &[&str]| {
+            source_aggregate(&copy_args(args), &config)
+                .unwrap()
+                .map(|a| a.target)
+        };
+        assert_eq!(
+            target(&["--pipeline-name", "orders"]),
+            Some(AggregateTarget::Collection("orders".to_string()))
+        );
+        assert_eq!(
+            target(&["--pipeline-index", "1"]),
+            Some(AggregateTarget::Database)
+        );
+        assert_eq!(
+            target(&["--pipeline-name", "items"]),
+            Some(AggregateTarget::Collection("items".to_string()))
+        );
+        assert_eq!(target(&[]), None);
+    }
+
+    #[test]
+    fn upserts_by_a_dotted_path() {
+        let document = doc! { "_id": 1, "meta": { "id": "a1" } };
+        assert_eq!(
+            upsert_statement(document.clone(), "meta.id").unwrap(),
+            doc! { "q": { "meta.id": "a1" }, "u": document.clone(), "upsert": true }
+        );
+        assert_eq!(
+            upsert_statement(document, "meta.name")
+                .unwrap_err()
+                .to_string(),
+            "Document is missing the 'meta.name' field"
+        );
+    }
+}
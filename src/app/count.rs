@@ -1,31 +1,65 @@
-use crate::shared::{keywords, Config, MongoDbCommand};
+use crate::backend::{Backend, MongoBackend};
+use crate::commands::Count;
+use crate::shared::{Config, FilterArgs};
 
-pub fn count_app() -> clap::App<'static, 'static> {
-    clap::App::new(MongoDbCommand::Count.to_str())
-        .about("Returns the number of documents that match a given filter")
-        .arg(
-            clap::Arg::with_name(keywords::INPUT_FILTER)
-                .long(keywords::INPUT_FILTER)
-                .help("The filter to be applied")
-                .takes_value(true)
-                .required(false),
-        )
+/// Return the number of documents that match the given filter
+#[derive(clap::Args, Debug)]
+pub struct CountArgs {
+    #[command(flatten)]
+    filter: FilterArgs,
 }
 
 pub fn handler(
-    matches: &clap::ArgMatches,
+    args: &CountArgs,
     config: Config,
+    out: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = mongodb::sync::Client::with_uri_str(&config.connection_uri)?;
-    let database = client.database(&config.database_name);
-    let collection =
-        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
-    let count_filter = matches
-        .value_of(keywords::INPUT_FILTER)
-        .map(|s| mongodb::bson::from_slice(s.as_bytes()))
-        .transpose()?;
-    let count_options = mongodb::options::CountOptions::builder().build();
-    let count = collection.count_documents(count_filter, count_options)?;
-    println!("{}", count);
+    let backend = MongoBackend::connect(&config.connection_uri)?;
+    run(args, &config, &backend, out)
+}
+
+pub fn run(
+    args: &CountArgs,
+    config: &Config,
+    backend: &dyn Backend,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let count = Count {
+        filter: args.filter.input_filter.clone(),
+    }
+    .run(backend, &config.database_name, &config.collection_name)?;
+    writeln!(out, "{}", count)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use crate::app::run_in_memory;
+    use crate::backend::{Backend, MemoryBackend};
+    use crate::shared::Config;
+
+    #[test]
+    fn counts_the_matching_documents() {
+        let backend = MemoryBackend::new();
+        backend
+            .insert(
+                "test",
+                "items",
+                vec![doc! { "tags": ["a", "b"] }, doc! { "tags": ["b"] }, doc! {}],
+            )
+            .unwrap();
+        let config = Config::new("mongodb://localhost", "test", "items");
+        let count = |args: &[&str]| run_in_memory(&backend, &config, args);
+        assert_eq!(count(&["count"]).unwrap(), "3\n");
+        assert_eq!(
+            count(&["count", "--input-filter", r#"{"tags": "b"}"#]).unwrap(),
+            "2\n"
+        );
+        assert!(count(&["count", "--input-filter", "[]"])
+            .unwrap_err()
+            .to_string()
+            .contains("invalid value '[]' for '--input-filter <INPUT_FILTER>': must be an object"));
+    }
+}
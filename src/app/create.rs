@@ -1,80 +1,200 @@
+use std::io::Write;
+
+use crate::backend::{Backend, MongoBackend};
+use crate::commands::Create;
 use crate::shared::{
-    convert_json_value_to_bson_document, create_values_from_reader, keywords, stringify_bson,
-    Config, InputType, InsertResult, MongoDbCommand,
+    convert_json_value_to_bson_document, create_values_from_reader, stringify_bson, Config,
+    InputType,
 };
+use crate::validation::{read_json_schema, validate_document, Violation};
 
-pub fn create_app() -> clap::App<'static, 'static> {
-    clap::App::new(MongoDbCommand::Create.to_str())
-        .about("Insert documents into the collection")
-        .arg(
-            clap::Arg::with_name(keywords::INPUT_DOCUMENTS)
-                .long(keywords::INPUT_DOCUMENTS)
-                .help("Get the documents directly as an argument. Supports JSON lines")
-                .takes_value(true)
-                .required(false),
-        )
-        .arg(
-            clap::Arg::with_name(keywords::INPUT_FILE)
-                .long(keywords::INPUT_FILE)
-                .help("Get the documents from a file. Support JSON lines")
-                .takes_value(true)
-                .required(false),
-        )
+/// Insert documents into the collection
+#[derive(clap::Args, Debug)]
+pub struct CreateArgs {
+    /// Get the documents directly as an argument. Supports JSON lines
+    #[arg(long)]
+    input_documents: Option<String>,
+    /// Get the documents from a file. Supports JSON lines
+    #[arg(long)]
+    input_file: Option<String>,
+    /// Validate every document against the $jsonSchema in this file before inserting. Nothing
+    /// is inserted if any document is invalid
+    #[arg(long)]
+    schema: Option<String>,
+    /// Insert the valid documents and write the invalid ones to the reject file
+    #[arg(long, requires = "schema")]
+    skip_invalid: bool,
+    /// Where the invalid documents are written to as JSON lines. Defaults to rejected.json
+    #[arg(long, requires = "skip_invalid")]
+    reject_file: Option<String>,
+}
+
+/// Split the values into the ones that pass the schema and the ones that do not, printing the
+/// violations of the latter.
+fn validate_values(
+    values: Vec<serde_json::Value>,
+    schema: &mongodb::bson::Document,
+    out: &mut dyn Write,
+) -> Result<(Vec<serde_json::Value>, Vec<serde_json::Value>), std::io::Error> {
+    let mut valid = Vec::with_capacity(values.len());
+    let mut invalid = Vec::new();
+    for (idx, value) in values.into_iter().enumerate() {
+        let violations = match convert_json_value_to_bson_document(&value) {
+            Some(document) => validate_document(schema, &document),
+            None => vec![Violation {
+                path: String::new(),
+                message: "only documents can be inserted".to_string(),
+            }],
+        };
+        if violations.is_empty() {
+            valid.push(value);
+        } else {
+            for violation in violations {
+                writeln!(out, "Document {}: {}", idx, violation)?;
+            }
+            invalid.push(value);
+        }
+    }
+    Ok((valid, invalid))
 }
 
 pub fn handler(
-    matches: &clap::ArgMatches,
+    args: &CreateArgs,
     config: Config,
+    out: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = mongodb::sync::Client::with_uri_str(&config.connection_uri)?;
-    let database = client.database(&config.database_name);
-    let collection =
-        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
-    let handle = if let Some(file) = matches.value_of(keywords::INPUT_FILE) {
-        InputType::BufReader(std::io::BufReader::new(std::fs::File::open(file)?))
-    } else if let Some(arg) = matches.value_of(keywords::INPUT_DOCUMENTS) {
-        // TODO: Possible to avoid allocation here?
-        InputType::Arg(arg.to_string())
-    } else if !atty::is(atty::Stream::Stdin) {
-        InputType::Stdin(std::io::stdin())
-    } else {
-        return Err("Please provide an input either by piping something in or specifying a file with '--input-file <file>'".into());
-    };
-    let doc = match handle {
-        InputType::Stdin(s) => create_values_from_reader(s.lock()),
-        InputType::Arg(s) => create_values_from_reader(std::io::BufReader::new(s.as_bytes())),
-        InputType::BufReader(b) => create_values_from_reader(b),
-    }?;
-    let result = match doc.as_slice() {
-        [doc] => InsertResult::One(collection.insert_one(
-            convert_json_value_to_bson_document(doc).expect("Only documents can be inserted"),
-            None,
-        )?),
-        o => InsertResult::Many(
-            collection.insert_many(
-                o.iter()
-                    .map(|s| convert_json_value_to_bson_document(s))
-                    .collect::<Option<Vec<_>>>()
-                    .expect("Only documents can be inserted"),
-                None,
-            )?,
-        ),
+    let backend = MongoBackend::connect(&config.connection_uri)?;
+    run(args, &config, &backend, out)
+}
+
+pub fn run(
+    args: &CreateArgs,
+    config: &Config,
+    backend: &dyn Backend,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let handle = InputType::from_args(
+        args.input_file.as_deref(),
+        args.input_documents.as_deref(),
+        "input-documents",
+    )?;
+    let mut doc = create_values_from_reader(handle.into_reader())?;
+    if let Some(schema_path) = &args.schema {
+        let total = doc.len();
+        let (valid, invalid) = validate_values(doc, &read_json_schema(schema_path)?, out)?;
+        if !invalid.is_empty() {
+            if !args.skip_invalid {
+                return Err(format!(
+                    "{} of {} document{} failed validation. Nothing was inserted",
+                    invalid.len(),
+                    total,
+                    if total == 1 { "" } else { "s" },
+                )
+                .into());
+            }
+            let reject_file = args.reject_file.as_deref().unwrap_or("rejected.json");
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(reject_file)?);
+            for value in invalid.iter() {
+                writeln!(writer, "{}", value)?;
+            }
+            writer.flush()?;
+            writeln!(
+                out,
+                "Skipped {} invalid document{}, written to {}",
+                invalid.len(),
+                if invalid.len() == 1 { "" } else { "s" },
+                reject_file
+            )?;
+        }
+        doc = valid;
+    }
+    if doc.is_empty() {
+        writeln!(out, "No documents to insert")?;
+        return Ok(());
+    }
+    let create = Create {
+        documents: doc
+            .iter()
+            .map(convert_json_value_to_bson_document)
+            .collect::<Option<Vec<_>>>()
+            .ok_or("Only documents can be inserted")?,
     };
-    match result {
-        InsertResult::One(o) => println!(
+    let inserted_ids = create.run(backend, &config.database_name, &config.collection_name)?;
+    if let [inserted_id] = inserted_ids.as_slice() {
+        writeln!(
+            out,
             "Successfully inserted one document with _id:{}\n",
-            stringify_bson(&o.inserted_id)
-        ),
-        InsertResult::Many(m) => {
-            println!(
-                "Successfully inserted {} document{} with _id:",
-                m.inserted_ids.len(),
-                if m.inserted_ids.len() == 1 { "" } else { "s" },
-            );
-            m.inserted_ids
-                .values()
-                .for_each(|s| println!("{}", stringify_bson(s)));
+            stringify_bson(inserted_id)
+        )?;
+    } else {
+        writeln!(
+            out,
+            "Successfully inserted {} document{} with _id:",
+            inserted_ids.len(),
+            if inserted_ids.len() == 1 { "" } else { "s" },
+        )?;
+        for id in inserted_ids.iter() {
+            writeln!(out, "{}", stringify_bson(id))?;
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use crate::app::run_in_memory;
+    use crate::backend::MemoryBackend;
+    use crate::shared::Config;
+
+    #[test]
+    fn inserts_json_documents_and_lines() {
+        let backend = MemoryBackend::new();
+        let config = Config::new("mongodb://localhost", "test", "items");
+        let output = run_in_memory(
+            &backend,
+            &config,
+            &["create", "--input-documents", r#"{"_id": "a", "n": 1}"#],
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "Successfully inserted one document with _id:\"a\"\n\n"
+        );
+
+        let output = run_in_memory(
+            &backend,
+            &config,
+            &[
+                "create",
+                "--input-documents",
+                "{\"_id\": \"b\", \"tags\": [true, null]}\n[{\"_id\": \"c\"}]",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "Successfully inserted 2 documents with _id:\n\"b\"\n\"c\"\n"
+        );
+        // JSON numbers are stored as doubles.
+        assert_eq!(
+            backend.documents("test", "items"),
+            vec![
+                doc! { "_id": "a", "n": 1.0 },
+                doc! { "_id": "b", "tags": [true, null] },
+                doc! { "_id": "c" },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_documents() {
+        let backend = MemoryBackend::new();
+        let config = Config::new("mongodb://localhost", "test", "items");
+        let error =
+            run_in_memory(&backend, &config, &["create", "--input-documents", "5"]).unwrap_err();
+        assert_eq!(error.to_string(), "Only documents can be inserted");
+        assert!(backend.documents("test", "items").is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+use prettytable::{Cell, Row, Table};
+
+use crate::shared::{connect, get_number, match_wildcards, Config, OutputFormat};
+
+/// The longest command printed in the table, in characters.
+const MAX_COMMAND_LENGTH: usize = 80;
+
+/// List the operations running on the server, longest running first
+#[derive(clap::Args, Debug)]
+pub struct CurrentOpArgs {
+    /// Only the operations running for at least this many seconds
+    #[arg(long)]
+    min_duration: Option<f64>,
+    /// Only the operations on the matching namespaces, e.g. 'shop.*'
+    #[arg(long)]
+    namespace: Option<String>,
+    /// Include the idle connections and the system operations
+    #[arg(long)]
+    all: bool,
+}
+
+/// The running operations, fetched with the `currentOp` command.
+pub fn current_ops(
+    client: &mongodb::sync::Client,
+    filter: mongodb::bson::Document,
+) -> Result<Vec<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+    let mut command = mongodb::bson::doc! { "currentOp": 1 };
+    command.extend(filter);
+    let reply = client.database("admin").run_command(command, None)?;
+    Ok(reply
+        .get_array("inprog")?
+        .iter()
+        .filter_map(|op| op.as_document().cloned())
+        .collect())
+}
+
+fn format_command(op: &mongodb::bson::Document) -> String {
+    let command = op
+        .get_document("command")
+        .map(|command| mongodb::bson::Bson::Document(command.clone()).into_relaxed_extjson())
+        .map(|command| command.to_string())
+        .unwrap_or_default();
+    if command.chars().count() > MAX_COMMAND_LENGTH {
+        let truncated = command
+            .chars()
+            .take(MAX_COMMAND_LENGTH - 3)
+            .collect::<String>();
+        format!("{}...", truncated)
+    } else {
+        command
+    }
+}
+
+/// One line per field of the operation that identifies it.
+pub fn describe_op(
+    op: &mongodb::bson::Document,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let field = |key: &str| {
+        op.get(key)
+            .map(|value| match value {
+                mongodb::bson::Bson::String(s) => s.clone(),
+                value => value.to_string(),
+            })
+            .unwrap_or_default()
+    };
+    writeln!(out, "Opid: {}", field("opid"))?;
+    writeln!(out, "Operation: {}", field("op"))?;
+    writeln!(out, "Namespace: {}", field("ns"))?;
+    writeln!(out, "Client: {}", field("client"))?;
+    writeln!(
+        out,
+        "Running for: {} s",
+        get_number(op, "secs_running").unwrap_or_default()
+    )?;
+    writeln!(out, "Command: {}", format_command(op))?;
+    Ok(())
+}
+
+pub fn handler(
+    args: &CurrentOpArgs,
+    output_format: OutputFormat,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let mut filter = mongodb::bson::Document::new();
+    if !args.all {
+        filter.insert("active", true);
+    }
+    if let Some(min_duration) = args.min_duration {
+        let microseconds = (min_duration * 1_000_000.0) as i64;
+        filter.insert(
+            "microsecs_running",
+            mongodb::bson::doc! { "$gte": microseconds },
+        );
+    }
+    let namespace = args.namespace.as_deref();
+    let mut ops = current_ops(&client, filter)?
+        .into_iter()
+        .filter(|op| {
+            namespace.map_or(true, |namespace| {
+                match_wildcards(namespace, op.get_str("ns").unwrap_or_default()).is_some()
+            })
+        })
+        // Leave out the currentOp command itself.
+        .filter(|op| {
+            op.get_document("command")
+                .map(|command| !command.contains_key("currentOp"))
+                .unwrap_or(true)
+        })
+        .collect::<Vec<_>>();
+    let running =
+        |op: &mongodb::bson::Document| get_number(op, "microsecs_running").unwrap_or_default();
+    ops.sort_by(|a, b| {
+        running(b)
+            .partial_cmp(&running(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    if output_format != OutputFormat::Document {
+        for op in ops {
+            writeln!(out, "{}", output_format.format_document(&op))?;
+        }
+        return Ok(());
+    }
+    if ops.is_empty() {
+        writeln!(out, "No operations are running")?;
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Opid"),
+        Cell::new("Running"),
+        Cell::new("Operation"),
+        Cell::new("Namespace"),
+        Cell::new("Client"),
+        Cell::new("Command"),
+    ]));
+    for op in ops.iter() {
+        table.add_row(Row::new(vec![
+            Cell::new(&op.get("opid").map(ToString::to_string).unwrap_or_default()),
+            Cell::new(&format!("{:.3} s", running(op) / 1_000_000.0)),
+            Cell::new(op.get_str("op").unwrap_or_default()),
+            Cell::new(op.get_str("ns").unwrap_or_default()),
+            Cell::new(op.get_str("client").unwrap_or_default()),
+            Cell::new(&format_command(op)),
+        ]));
+    }
+    table.print(out)?;
+    writeln!(
+        out,
+        "{} operation{}",
+        ops.len(),
+        if ops.len() == 1 { "" } else { "s" }
+    )?;
+    Ok(())
+}
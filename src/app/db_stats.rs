@@ -0,0 +1,51 @@
+use crate::shared::{connect, format_size, get_number, print_fields, Config, OutputFormat};
+
+/// The fields of `dbStats` that are printed, with their labels and whether they are sizes.
+const FIELDS: &[(&str, &str, bool)] = &[
+    ("collections", "Collections", false),
+    ("views", "Views", false),
+    ("objects", "Documents", false),
+    ("avgObjSize", "Average document size", true),
+    ("dataSize", "Data size", true),
+    ("storageSize", "Storage size", true),
+    ("indexes", "Indexes", false),
+    ("indexSize", "Index size", true),
+    ("totalSize", "Total size", true),
+    ("fsUsedSize", "Filesystem used", true),
+    ("fsTotalSize", "Filesystem size", true),
+];
+
+pub fn handler(
+    output_format: OutputFormat,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let stats = client
+        .database(&config.database_name)
+        .run_command(mongodb::bson::doc! { "dbStats": 1 }, None)?;
+    let mut summary = mongodb::bson::doc! { "db": &config.database_name };
+    for (field, _, _) in FIELDS {
+        if let Some(value) = stats.get(field) {
+            summary.insert(*field, value.clone());
+        }
+    }
+    if output_format != OutputFormat::Document {
+        writeln!(out, "{}", output_format.format_document(&summary))?;
+        return Ok(());
+    }
+
+    let mut fields = vec![("Database", config.database_name.clone())];
+    for (field, label, is_size) in FIELDS {
+        // Older servers do not report all of them.
+        if let Some(value) = get_number(&summary, field) {
+            let value = if *is_size {
+                format_size(value)
+            } else {
+                value.to_string()
+            };
+            fields.push((label, value));
+        }
+    }
+    print_fields(fields, out)
+}
@@ -1,35 +1,84 @@
-use crate::shared::{
-    convert_json_value_to_bson_document, delete_args, keywords, Config, MongoDbCommand,
-};
+use crate::backend::{Backend, MongoBackend};
+use crate::commands::Delete;
+use crate::shared::{Config, FilterArgs};
 
-pub fn delete_many_app() -> clap::App<'static, 'static> {
-    clap::App::new(MongoDbCommand::DeleteMany.to_str())
-        .about("Delete the documents that match a given filter")
-        .args(&delete_args())
+/// Delete the documents that match the given filter
+#[derive(clap::Args, Debug)]
+pub struct DeleteManyArgs {
+    #[command(flatten)]
+    filter: FilterArgs,
 }
 
 pub fn handler(
-    matches: &clap::ArgMatches,
+    args: &DeleteManyArgs,
     config: Config,
+    out: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = mongodb::sync::Client::with_uri_str(&config.connection_uri)?;
-    let database = client.database(&config.database_name);
-    let collection =
-        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
-    let delete_many_filter = matches
-        .value_of(keywords::INPUT_FILTER)
-        .map(|s| serde_json::from_slice::<serde_json::Value>(s.as_bytes()))
-        .transpose()?
-        .map(|v| convert_json_value_to_bson_document(&v))
-        .flatten()
-        .unwrap_or_default();
-    let cursor = collection
-        .delete_many(delete_many_filter, None)?
-        .deleted_count;
-    println!(
+    let backend = MongoBackend::connect(&config.connection_uri)?;
+    run(args, &config, &backend, out)
+}
+
+pub fn run(
+    args: &DeleteManyArgs,
+    config: &Config,
+    backend: &dyn Backend,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cursor = Delete {
+        filter: args.filter.input_filter.clone().unwrap_or_default(),
+        many: true,
+    }
+    .run(backend, &config.database_name, &config.collection_name)?;
+    writeln!(
+        out,
         "Deleted {} document{}",
         cursor,
         if cursor == 1 { "" } else { "s" }
-    );
+    )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use crate::app::run_in_memory;
+    use crate::backend::{Backend, MemoryBackend};
+    use crate::shared::Config;
+
+    #[test]
+    fn deletes_the_matching_documents() {
+        let backend = MemoryBackend::new();
+        backend
+            .insert(
+                "test",
+                "items",
+                vec![
+                    doc! { "_id": 1, "n": 1 },
+                    doc! { "_id": 2, "n": 2 },
+                    doc! { "_id": 3, "n": 3 },
+                ],
+            )
+            .unwrap();
+        let config = Config::new("mongodb://localhost", "test", "items");
+        let output = run_in_memory(
+            &backend,
+            &config,
+            &["delete-many", "--input-filter", r#"{"n": {"$lte": 2}}"#],
+        )
+        .unwrap();
+        assert_eq!(output, "Deleted 2 documents\n");
+        assert_eq!(
+            backend.documents("test", "items"),
+            vec![doc! { "_id": 3, "n": 3 }]
+        );
+
+        let output = run_in_memory(
+            &backend,
+            &config,
+            &["delete-one", "--input-filter", r#"{"n": 3}"#],
+        )
+        .unwrap();
+        assert_eq!(output, "Deleted 1 document\n");
+    }
+}
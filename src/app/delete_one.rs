@@ -1,35 +1,39 @@
-use crate::shared::{
-    convert_json_value_to_bson_document, delete_args, keywords, Config, MongoDbCommand,
-};
+use crate::backend::{Backend, MongoBackend};
+use crate::commands::Delete;
+use crate::shared::{Config, FilterArgs};
 
-pub fn delete_one_app() -> clap::App<'static, 'static> {
-    clap::App::new(MongoDbCommand::DeleteOne.to_str())
-        .about("Delete the first document that matches a given filter")
-        .args(&delete_args())
+/// Delete the first document that matches the given filter
+#[derive(clap::Args, Debug)]
+pub struct DeleteOneArgs {
+    #[command(flatten)]
+    filter: FilterArgs,
 }
 
 pub fn handler(
-    matches: &clap::ArgMatches,
+    args: &DeleteOneArgs,
     config: Config,
+    out: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = mongodb::sync::Client::with_uri_str(&config.connection_uri)?;
-    let database = client.database(&config.database_name);
-    let collection =
-        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
-    let delete_one_filter = matches
-        .value_of(keywords::INPUT_FILTER)
-        .map(|s| serde_json::from_slice::<serde_json::Value>(s.as_bytes()))
-        .transpose()?
-        .map(|v| convert_json_value_to_bson_document(&v))
-        .flatten()
-        .unwrap_or_default();
-    let cursor = collection
-        .delete_one(delete_one_filter, None)?
-        .deleted_count;
-    println!(
+    let backend = MongoBackend::connect(&config.connection_uri)?;
+    run(args, &config, &backend, out)
+}
+
+pub fn run(
+    args: &DeleteOneArgs,
+    config: &Config,
+    backend: &dyn Backend,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cursor = Delete {
+        filter: args.filter.input_filter.clone().unwrap_or_default(),
+        many: false,
+    }
+    .run(backend, &config.database_name, &config.collection_name)?;
+    writeln!(
+        out,
         "Deleted {} document{}",
         cursor,
         if cursor == 1 { "" } else { "s" }
-    );
+    )?;
     Ok(())
 }
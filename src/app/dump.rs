@@ -0,0 +1,81 @@
+use std::io::Write;
+
+use crate::shared::{connect, first_batch, list_index_specs, parse_document, Config};
+
+/// Write the documents, indexes and options of the collection into a directory
+#[derive(clap::Args, Debug)]
+pub struct DumpArgs {
+    /// Only dump the documents that match this filter
+    #[arg(long, value_parser = parse_document)]
+    input_filter: Option<mongodb::bson::Document>,
+    /// The directory to write into. Files are written to '<dir>/<database>/<collection>.bson'
+    /// and '<dir>/<database>/<collection>.metadata.json'
+    #[arg(long, default_value = "dump")]
+    directory: String,
+}
+
+pub fn handler(
+    args: &DumpArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let database = client.database(&config.database_name);
+    let collection =
+        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
+    let dump_filter = args.input_filter.clone();
+
+    let collections = database.run_command(
+        mongodb::bson::doc! {
+            "listCollections": 1,
+            "filter": { "name": &config.collection_name },
+        },
+        None,
+    )?;
+    // Checked before anything is written so a missing collection leaves no directory behind.
+    let options = first_batch(&collections)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            format!(
+                "The collection {}.{} does not exist",
+                config.database_name, config.collection_name
+            )
+        })?
+        .get_document("options")
+        .ok()
+        .cloned()
+        .unwrap_or_default();
+    let indexes = list_index_specs(&database, &config.collection_name)?;
+    let metadata = serde_json::json!({
+        "collectionName": &config.collection_name,
+        "options": mongodb::bson::Bson::Document(options).into_canonical_extjson(),
+        "indexes": indexes
+            .into_iter()
+            .map(|i| mongodb::bson::Bson::Document(i).into_canonical_extjson())
+            .collect::<Vec<_>>(),
+    });
+    let directory = std::path::Path::new(&args.directory).join(&config.database_name);
+    std::fs::create_dir_all(&directory)?;
+    let metadata_path = directory.join(format!("{}.metadata.json", config.collection_name));
+    std::fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+
+    let bson_path = directory.join(format!("{}.bson", config.collection_name));
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(&bson_path)?);
+    let mut count = 0;
+    for result in collection.find(dump_filter, None)? {
+        result?.to_writer(&mut writer)?;
+        count += 1;
+    }
+    writer.flush()?;
+    writeln!(
+        out,
+        "Dumped {} document{} from {}.{} to {}",
+        count,
+        if count == 1 { "" } else { "s" },
+        config.database_name,
+        config.collection_name,
+        bson_path.display()
+    )?;
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use std::convert::TryFrom;
+
+use crate::shared::{confirm, connect, parse_document, Config};
+
+/// Edit the first document that matches the given filter in $EDITOR
+#[derive(clap::Args, Debug)]
+pub struct EditArgs {
+    /// The filter to be applied
+    #[arg(long, value_parser = parse_document)]
+    input_filter: mongodb::bson::Document,
+    /// Replace the document without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
+}
+
+fn to_json(value: &mongodb::bson::Bson) -> serde_json::Value {
+    value.clone().into_relaxed_extjson()
+}
+
+/// The edited document loses the types Extended JSON does not preserve, e.g. an Int64 that
+/// fits an Int32. Values the user did not change are taken from the original document instead.
+fn restore_types(
+    original: &mongodb::bson::Document,
+    edited: mongodb::bson::Document,
+) -> mongodb::bson::Document {
+    edited
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match (original.get(&key), value) {
+                (
+                    Some(mongodb::bson::Bson::Document(original)),
+                    mongodb::bson::Bson::Document(edited),
+                ) => mongodb::bson::Bson::Document(restore_types(original, edited)),
+                (Some(original), value) if to_json(original) == to_json(&value) => original.clone(),
+                (_, value) => value,
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+/// One line per added, removed or changed field. Embedded documents are compared field by
+/// field.
+fn diff(
+    path: &str,
+    original: &mongodb::bson::Document,
+    edited: &mongodb::bson::Document,
+    lines: &mut Vec<String>,
+) {
+    let field_path = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    for (key, value) in original {
+        match edited.get(key) {
+            None => lines.push(format!("- {}: {}", field_path(key), to_json(value))),
+            Some(mongodb::bson::Bson::Document(edited)) => {
+                if let mongodb::bson::Bson::Document(original) = value {
+                    diff(&field_path(key), original, edited, lines);
+                } else {
+                    lines.push(format!(
+                        "~ {}: {} -> {}",
+                        field_path(key),
+                        to_json(value),
+                        to_json(&mongodb::bson::Bson::Document(edited.clone()))
+                    ));
+                }
+            }
+            Some(edited) if edited != value => lines.push(format!(
+                "~ {}: {} -> {}",
+                field_path(key),
+                to_json(value),
+                to_json(edited)
+            )),
+            Some(_) => {}
+        }
+    }
+    for (key, value) in edited {
+        if !original.contains_key(key) {
+            lines.push(format!("+ {}: {}", field_path(key), to_json(value)));
+        }
+    }
+}
+
+/// A file only the current user can read, removed when dropped so it is not left behind
+/// whichever way the edit ends.
+struct TempFile {
+    path: std::path::PathBuf,
+}
+
+impl TempFile {
+    /// Fails instead of following a file or link someone else created under the same name.
+    fn create(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .subsec_nanos();
+        let path = std::env::temp_dir().join(format!(
+            "magg-edit-{}-{:08x}.json",
+            std::process::id(),
+            nanos
+        ));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&path)?;
+        let temp_file = TempFile { path };
+        std::io::Write::write_all(&mut file, contents.as_bytes())?;
+        Ok(temp_file)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Open the file in $EDITOR, falling back to vi.
+fn run_editor(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    // $EDITOR may contain arguments, e.g. "code --wait".
+    let mut words = shell_words::split(&editor)?;
+    if words.is_empty() {
+        return Err("$EDITOR is empty".into());
+    }
+    let program = words.remove(0);
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(format!("The editor exited with {}", status).into());
+    }
+    Ok(())
+}
+
+pub fn handler(
+    args: &EditArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let database = client.database(&config.database_name);
+    let collection =
+        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
+    let original = collection
+        .find_one(args.input_filter.clone(), None)?
+        .ok_or("No document matches the filter")?;
+
+    let original_json =
+        serde_json::to_string_pretty(&to_json(&mongodb::bson::Bson::Document(original.clone())))?;
+    let temp_file = TempFile::create(&original_json)?;
+    run_editor(&temp_file.path)?;
+    let edited_json = std::fs::read_to_string(&temp_file.path)?;
+    drop(temp_file);
+    let edited = match serde_json::from_str::<serde_json::Value>(&edited_json)? {
+        serde_json::Value::Object(o) => {
+            restore_types(&original, mongodb::bson::Document::try_from(o)?)
+        }
+        _ => return Err("The document must be an object".into()),
+    };
+
+    let mut lines = vec![];
+    diff("", &original, &edited, &mut lines);
+    if lines.is_empty() {
+        writeln!(out, "No changes")?;
+        return Ok(());
+    }
+    if edited.get("_id") != original.get("_id") {
+        return Err("The _id of a document cannot be changed".into());
+    }
+    for line in &lines {
+        writeln!(out, "{}", line)?;
+    }
+    if !args.yes && !confirm("Replace the document?", out)? {
+        writeln!(out, "Nothing was replaced")?;
+        return Ok(());
+    }
+
+    // Only replace the document if it is still exactly the one that was edited.
+    let mut guard = mongodb::bson::doc! {
+        "$expr": { "$eq": ["$$ROOT", { "$literal": original.clone() }] },
+    };
+    if let Some(id) = original.get("_id") {
+        guard.insert("_id", id.clone());
+    }
+    let result = collection.replace_one(guard, edited, None)?;
+    if result.matched_count == 0 {
+        return Err(
+            "The document was modified or deleted since it was fetched. \
+            Nothing was replaced"
+                .into(),
+        );
+    }
+    writeln!(
+        out,
+        "Replaced the document ({} field{} changed)",
+        lines.len(),
+        if lines.len() == 1 { "" } else { "s" }
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::app::{Command, Magg};
+
+    #[test]
+    fn parses_the_filter_as_extended_json() {
+        let magg = Magg::try_parse_from([
+            "magg",
+            "edit",
+            "--input-filter",
+            r#"{"_id": {"$oid": "5f1d2b3c4d5e6f7a8b9c0d1e"}, "n": 1}"#,
+        ])
+        .unwrap();
+        let args = match magg.command {
+            Some(Command::Edit(args)) => args,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            args.input_filter,
+            mongodb::bson::doc! {
+                "_id": mongodb::bson::oid::ObjectId::parse_str("5f1d2b3c4d5e6f7a8b9c0d1e").unwrap(),
+                "n": 1,
+            }
+        );
+    }
+
+    #[test]
+    fn removes_the_temporary_file_when_dropped() {
+        let temp_file = TempFile::create("{}").unwrap();
+        let path = temp_file.path.clone();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        drop(temp_file);
+        assert!(!path.exists());
+    }
+}
@@ -1,51 +1,157 @@
-use crate::shared::{
-    convert_json_value_to_bson_document, find_one_args, keywords, stringify_document, Config,
-    MongoDbCommand,
-};
-
-pub fn find_many_app() -> clap::App<'static, 'static> {
-    let mut args = find_one_args();
-    args.push(
-        clap::Arg::with_name(keywords::LIMIT)
-            .long(keywords::LIMIT)
-            .help("Limit the result to N documents")
-            .takes_value(true)
-            .required(false),
-    );
-    clap::App::new(MongoDbCommand::FindMany.to_str())
-        .about("find all the documents that matches the given filter")
-        .args(&args)
+use prettytable::{Cell, Row, Table};
+
+use crate::backend::{Backend, MongoBackend};
+use crate::commands::Find;
+use crate::shared::{parse_document, Config, FindArgs, OutputFormat};
+
+/// Find all the documents that match the given filter
+#[derive(clap::Args, Debug)]
+pub struct FindManyArgs {
+    #[command(flatten)]
+    find: FindArgs,
+    /// Limit the result to N documents
+    #[arg(long)]
+    limit: Option<i64>,
+    /// Sort the resulting documents, e.g. {"createdAt": -1}
+    #[arg(long, value_parser = parse_document)]
+    sort: Option<mongodb::bson::Document>,
+    /// Name of the saved query to be run. See --list. The other arguments override the parts of
+    /// the saved query
+    #[arg(long)]
+    query_name: Option<String>,
+    /// List all saved queries from the configuration file. Will be empty if '--config-file' is
+    /// not passed
+    #[arg(long)]
+    list: bool,
+}
+
+fn list_queries(
+    config: &Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Index"),
+        Cell::new("Name"),
+        Cell::new("Description"),
+    ]));
+    for (idx, q) in config.queries.iter().enumerate() {
+        table.add_row(Row::new(vec![
+            Cell::new(format!("{}", idx).as_str()),
+            Cell::new(q.name.as_str()),
+            Cell::new(q.description.text().as_str()),
+        ]));
+    }
+    table.print(out)?;
+    Ok(())
 }
 
 pub fn handler(
-    matches: &clap::ArgMatches,
+    args: &FindManyArgs,
+    output_format: OutputFormat,
     config: Config,
+    out: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = mongodb::sync::Client::with_uri_str(&config.connection_uri)?;
-    let database = client.database(&config.database_name);
-    let collection =
-        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
-    let find_filter = matches
-        .value_of(keywords::INPUT_FILTER)
-        .map(|s| mongodb::bson::from_slice(s.as_bytes()))
-        .transpose()?;
-    let find_limit = matches
-        .value_of(keywords::LIMIT)
-        .map(|s| i64::from_str_radix(s, 10))
+    let backend = MongoBackend::connect(&config.connection_uri)?;
+    run(args, output_format, &config, &backend, out)
+}
+
+pub fn run(
+    args: &FindManyArgs,
+    output_format: OutputFormat,
+    config: &Config,
+    backend: &dyn Backend,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.list {
+        list_queries(config, out)?;
+        return Ok(());
+    }
+    let query = args
+        .query_name
+        .as_ref()
+        .map(|name| config.query_by_name(name))
         .transpose()?;
-    let find_project = matches
-        .value_of(keywords::PROJECT)
-        .map(|s| serde_json::from_slice::<serde_json::Value>(s.as_bytes()))
-        .transpose()?
-        .map(|v| convert_json_value_to_bson_document(&v))
-        .unwrap_or_default();
-    let find_options = mongodb::options::FindOptions::builder()
-        .limit(find_limit)
-        .projection(find_project)
-        .build();
-    let cursor = collection.find(find_filter, find_options)?;
-    for result in cursor {
-        println!("{}", stringify_document(&result?));
+    let find_filter = args
+        .find
+        .filter
+        .input_filter
+        .clone()
+        .or_else(|| query.map(|q| q.filter.clone()));
+    let find_limit = args.limit.or_else(|| query.and_then(|q| q.limit));
+    let find_project = args
+        .find
+        .project
+        .clone()
+        .or_else(|| query.and_then(|q| q.projection.clone()));
+    let find_sort = args
+        .sort
+        .clone()
+        .or_else(|| query.and_then(|q| q.sort.clone()));
+    let find = Find {
+        filter: find_filter,
+        projection: find_project,
+        sort: find_sort,
+        limit: find_limit,
+    };
+    for result in find.run(backend, &config.database_name, &config.collection_name)? {
+        writeln!(out, "{}", output_format.format_document(&result?))?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{run_in_memory, seeded_backend, test_config};
+
+    #[test]
+    fn applies_the_filter_sort_and_limit() {
+        let output = run_in_memory(
+            &seeded_backend(),
+            &test_config(),
+            &[
+                "--output-format",
+                "json",
+                "find-many",
+                "--input-filter",
+                r#"{"price": {"$gte": 2}}"#,
+                "--sort",
+                r#"{"name": -1}"#,
+                "--limit",
+                "2",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "{\"_id\":3,\"name\":\"cherry\",\"price\":10,\"tags\":[\"fruit\"]}\n\
+            {\"_id\":2,\"name\":\"bread\",\"price\":2,\"stock\":{\"count\":0}}\n"
+        );
+    }
+
+    #[test]
+    fn overrides_parts_of_a_saved_query() {
+        let output = run_in_memory(
+            &seeded_backend(),
+            &test_config(),
+            &[
+                "--output-format",
+                "json",
+                "find-many",
+                "--query-name",
+                "expensive",
+                "--limit",
+                "1",
+            ],
+        )
+        .unwrap();
+        assert_eq!(output, "{\"_id\":3,\"name\":\"cherry\"}\n");
+    }
+
+    #[test]
+    fn lists_the_saved_queries() {
+        let output =
+            run_in_memory(&seeded_backend(), &test_config(), &["find-many", "--list"]).unwrap();
+        assert!(output.contains("| 0     | expensive | Most expensive first |"));
+    }
+}
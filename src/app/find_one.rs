@@ -1,40 +1,78 @@
-use crate::shared::{
-    convert_json_value_to_bson_document, find_one_args, keywords, stringify_document, Config,
-    MongoDbCommand,
-};
+use crate::backend::{Backend, MongoBackend};
+use crate::commands::Find;
+use crate::shared::{Config, FindArgs, OutputFormat};
 
-pub fn find_one_app() -> clap::App<'static, 'static> {
-    clap::App::new(MongoDbCommand::FindOne.to_str())
-        .about("Find the first document that matches a given filter")
-        .args(&find_one_args())
+/// Find the first document that matches the given filter
+#[derive(clap::Args, Debug)]
+pub struct FindOneArgs {
+    #[command(flatten)]
+    find: FindArgs,
 }
 
 pub fn handler(
-    matches: &clap::ArgMatches,
+    args: &FindOneArgs,
+    output_format: OutputFormat,
     config: Config,
+    out: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = mongodb::sync::Client::with_uri_str(&config.connection_uri)?;
-    let database = client.database(&config.database_name);
-    let collection =
-        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
-    let find_filter = matches
-        .value_of(keywords::INPUT_FILTER)
-        .map(|s| mongodb::bson::from_slice(s.as_bytes()))
-        .transpose()?;
-    let find_project = matches
-        .value_of(keywords::PROJECT)
-        .map(|s| serde_json::from_slice::<serde_json::Value>(s.as_bytes()))
-        .transpose()?
-        .map(|v| convert_json_value_to_bson_document(&v))
-        .unwrap_or_default();
-    let find_one_options = mongodb::options::FindOneOptions::builder()
-        .projection(find_project)
-        .build();
-    let cursor = collection.find_one(find_filter, find_one_options)?;
-    if let Some(result) = cursor {
-        println!("{}", stringify_document(&result));
+    let backend = MongoBackend::connect(&config.connection_uri)?;
+    run(args, output_format, &config, &backend, out)
+}
+
+pub fn run(
+    args: &FindOneArgs,
+    output_format: OutputFormat,
+    config: &Config,
+    backend: &dyn Backend,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let find = Find {
+        filter: args.find.filter.input_filter.clone(),
+        projection: args.find.project.clone(),
+        ..Default::default()
+    };
+    if let Some(result) = find.run_one(backend, &config.database_name, &config.collection_name)? {
+        writeln!(out, "{}", output_format.format_document(&result))?;
     } else {
-        println!("No such documents");
+        writeln!(out, "No such documents")?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{run_in_memory, seeded_backend, test_config};
+
+    #[test]
+    fn parses_the_filter_as_json() {
+        let output = run_in_memory(
+            &seeded_backend(),
+            &test_config(),
+            &[
+                "--output-format",
+                "json",
+                "find-one",
+                "--input-filter",
+                r#"{"price": {"$lt": 3}}"#,
+                "--project",
+                r#"{"_id": 0}"#,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "{\"name\":\"bread\",\"price\":2,\"stock\":{\"count\":0}}\n"
+        );
+    }
+
+    #[test]
+    fn reports_when_nothing_matches() {
+        let output = run_in_memory(
+            &seeded_backend(),
+            &test_config(),
+            &["find-one", "--input-filter", r#"{"name": "egg"}"#],
+        )
+        .unwrap();
+        assert_eq!(output, "No such documents\n");
+    }
+}
@@ -0,0 +1,87 @@
+use clap::Parser;
+
+use super::{to_handler, Magg};
+use crate::history::{read_entries, record};
+use crate::shared::Config;
+
+/// What `logging::redact_uri` puts in place of a password.
+const REDACTED_PASSWORD: &str = ":***@";
+
+/// List, search and re-run the previously executed commands
+#[derive(clap::Args, Debug)]
+pub struct HistoryArgs {
+    /// Only list the commands containing this text
+    #[arg(long)]
+    search: Option<String>,
+    /// Only list the N most recent commands
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Run the command with this number again
+    #[arg(long, conflicts_with_all = ["search", "limit"])]
+    rerun: Option<usize>,
+}
+
+/// Run a recorded command. The connection, database and collection of the current invocation
+/// are used unless the recorded command names its own.
+fn rerun(
+    args: &[String],
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut magg = Magg::try_parse_from(
+        std::iter::once(clap::crate_name!()).chain(args.iter().map(String::as_str)),
+    )?;
+    // Passwords are not recorded, so the current connection is used instead.
+    if magg
+        .global
+        .connection_uri
+        .as_ref()
+        .is_some_and(|uri| uri.contains(REDACTED_PASSWORD))
+    {
+        magg.global.connection_uri = None;
+    }
+    let config = config.with_overrides(&magg.global)?;
+    let result = to_handler(magg, config, out);
+    record(args, result.is_ok())?;
+    result
+}
+
+/// Listing the history needs no configuration, so a missing one is only reported when a
+/// command is re-run.
+pub fn handler(
+    args: &HistoryArgs,
+    config: Result<Config, Box<dyn std::error::Error>>,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = read_entries()?;
+    if let Some(number) = args.rerun {
+        let entry = number
+            .checked_sub(1)
+            .and_then(|index| entries.get(index))
+            .ok_or_else(|| format!("There are no command number {}", number))?;
+        eprintln!("{}", entry.command_line());
+        return rerun(&entry.args, config?, out);
+    }
+
+    let search = args.search.as_deref();
+    let mut listed = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (index + 1, entry, entry.command_line()))
+        .filter(|(_, _, command_line)| search.map_or(true, |s| command_line.contains(s)))
+        .collect::<Vec<_>>();
+    if let Some(limit) = args.limit {
+        listed.drain(..listed.len().saturating_sub(limit));
+    }
+    for (number, entry, command_line) in listed {
+        writeln!(
+            out,
+            "{:>5}  {}  {}{}",
+            number,
+            entry.local_time(),
+            command_line,
+            if entry.success { "" } else { "  (failed)" }
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use crate::app::current_op::{current_ops, describe_op};
+use crate::shared::{confirm, connect, Config};
+
+/// Kill a running operation, as listed by current-op
+#[derive(clap::Args, Debug)]
+pub struct KillOpArgs {
+    /// The opid of the operation
+    opid: String,
+    /// Kill the operation without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
+}
+
+/// The opid is a number on a replica set and a "shard:number" string through mongos.
+fn parse_opid(opid: &str) -> mongodb::bson::Bson {
+    if let Ok(opid) = opid.parse::<i32>() {
+        mongodb::bson::Bson::Int32(opid)
+    } else if let Ok(opid) = opid.parse::<i64>() {
+        mongodb::bson::Bson::Int64(opid)
+    } else {
+        mongodb::bson::Bson::String(opid.to_string())
+    }
+}
+
+pub fn handler(
+    args: &KillOpArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let opid = args.opid.as_str();
+    let client = connect(&config.connection_uri)?;
+    let op = current_ops(&client, mongodb::bson::doc! { "opid": parse_opid(opid) })?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("There is no operation with opid {}", opid))?;
+    describe_op(&op, out)?;
+    if !args.yes && !confirm(&format!("Kill operation {}?", opid), out)? {
+        writeln!(out, "Nothing was killed")?;
+        return Ok(());
+    }
+
+    client.database("admin").run_command(
+        mongodb::bson::doc! { "killOp": 1, "op": parse_opid(opid) },
+        None,
+    )?;
+    writeln!(out, "Killed operation {}", opid)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_opid() {
+        assert_eq!(parse_opid("42"), mongodb::bson::Bson::Int32(42));
+        assert_eq!(
+            parse_opid("8589934592"),
+            mongodb::bson::Bson::Int64(8589934592)
+        );
+        assert_eq!(
+            parse_opid("shard01:1234"),
+            mongodb::bson::Bson::String("shard01:1234".to_string())
+        );
+    }
+}
@@ -1,16 +1,92 @@
-use crate::shared::{Config, MongoDbCommand};
+use prettytable::{Cell, Row, Table};
 
-pub fn list_databases_app() -> clap::App<'static, 'static> {
-    clap::App::new(MongoDbCommand::ListDatabases.to_str())
-        .about("find all the documents that matches the given filter")
+use crate::backend::{Backend, MongoBackend};
+use crate::shared::{format_size, Config, OutputFormat};
+
+pub fn handler(
+    output_format: OutputFormat,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = MongoBackend::connect(&config.connection_uri)?;
+    run(output_format, &config, &backend, out)
 }
 
-// TODO: Print as a table? Prettify option?
-pub fn handler(_: &clap::ArgMatches, config: Config) -> Result<(), Box<dyn std::error::Error>> {
-    let client = mongodb::sync::Client::with_uri_str(&config.connection_uri)?;
-    let result = client.list_databases(None, None)?;
-    for x in result {
-        println!("{:#?}", x);
+pub fn run(
+    output_format: OutputFormat,
+    _: &Config,
+    backend: &dyn Backend,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let databases = backend.list_databases()?;
+    if output_format != OutputFormat::Document {
+        for database in databases {
+            let document = mongodb::bson::doc! {
+                "name": database.name,
+                "sizeOnDisk": database.size_on_disk as i64,
+                "empty": database.empty,
+            };
+            writeln!(out, "{}", output_format.format_document(&document))?;
+        }
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Name"),
+        Cell::new("Size on disk"),
+        Cell::new("Empty"),
+    ]));
+    for database in databases {
+        table.add_row(Row::new(vec![
+            Cell::new(&database.name),
+            Cell::new(&format_size(database.size_on_disk as f64)),
+            Cell::new(if database.empty { "yes" } else { "no" }),
+        ]));
     }
+    table.print(out)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use crate::app::run_in_memory;
+    use crate::backend::{Backend, MemoryBackend};
+    use crate::shared::{format_size, Config};
+
+    #[test]
+    fn prints_the_sizes() {
+        let backend = MemoryBackend::new();
+        backend
+            .insert("test", "items", vec![doc! { "_id": 1, "name": "apple" }])
+            .unwrap();
+        let config = Config::new("mongodb://localhost", "test", "items");
+        assert_eq!(
+            run_in_memory(&backend, &config, &["list-databases"]).unwrap(),
+            "+------+--------------+-------+\n\
+            | Name | Size on disk | Empty |\n\
+            +------+--------------+-------+\n\
+            | test | 30 B         | no    |\n\
+            +------+--------------+-------+\n"
+        );
+        assert_eq!(
+            run_in_memory(
+                &backend,
+                &config,
+                &["--output-format", "json", "list-databases"]
+            )
+            .unwrap(),
+            "{\"name\":\"test\",\"sizeOnDisk\":30,\"empty\":false}\n"
+        );
+    }
+
+    #[test]
+    fn formats_sizes_in_binary_units() {
+        assert_eq!(format_size(0.0), "0 B");
+        assert_eq!(format_size(1023.0), "1023 B");
+        assert_eq!(format_size(1536.0), "1.5 KiB");
+        assert_eq!(format_size(5.0 * 1024.0 * 1024.0 * 1024.0), "5.0 GiB");
+    }
+}
@@ -0,0 +1,82 @@
+/// The width the help texts are wrapped to in the man page.
+const WIDTH: usize = 80;
+
+/// Escape a line of text for roff, where backslashes start escapes and a leading dot or
+/// quote starts a request.
+fn escape(line: &str) -> String {
+    let line = line.replace('\\', "\\e").replace('-', "\\-");
+    if line.starts_with('.') || line.starts_with('\'') {
+        format!("\\&{}", line)
+    } else {
+        line
+    }
+}
+
+fn long_help(mut command: clap::Command) -> Result<String, Box<dyn std::error::Error>> {
+    let mut help = Vec::new();
+    command.write_long_help(&mut help)?;
+    Ok(String::from_utf8(help)?)
+}
+
+/// The help text as is, without filling or adjusting the lines.
+fn write_help(help: &str, out: &mut dyn std::io::Write) -> Result<(), Box<dyn std::error::Error>> {
+    writeln!(out, ".nf")?;
+    for line in help.trim_end().lines() {
+        writeln!(out, "{}", escape(line.trim_end()))?;
+    }
+    writeln!(out, ".fi")?;
+    Ok(())
+}
+
+pub fn handler(out: &mut dyn std::io::Write) -> Result<(), Box<dyn std::error::Error>> {
+    let name = clap::crate_name!();
+    writeln!(
+        out,
+        ".TH {} 1 \"\" \"{} {}\" \"User Commands\"",
+        name.to_uppercase(),
+        name,
+        clap::crate_version!()
+    )?;
+    writeln!(out, ".SH NAME")?;
+    writeln!(out, "{} \\- {}", name, escape(clap::crate_description!()))?;
+    writeln!(out, ".SH SYNOPSIS")?;
+    writeln!(out, ".B {}", name)?;
+    writeln!(out, "[OPTIONS] [COMMAND]")?;
+    writeln!(out, ".SH DESCRIPTION")?;
+    // Built so that the subcommands have the global arguments and their full usage.
+    let mut main = super::main_app().term_width(WIDTH);
+    main.build();
+    write_help(&long_help(main.clone())?, out)?;
+    writeln!(out, ".SH SUBCOMMANDS")?;
+    for command in main.get_subcommands() {
+        if command.is_hide_set() || command.get_name() == "help" {
+            continue;
+        }
+        writeln!(out, ".SS {}", escape(command.get_name()))?;
+        write_help(&long_help(command.clone())?, out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_roff() {
+        assert_eq!(escape("--limit <limit>"), "\\-\\-limit <limit>");
+        assert_eq!(escape(".hidden"), "\\&.hidden");
+        assert_eq!(escape("a \\ b"), "a \\e b");
+    }
+
+    #[test]
+    fn documents_every_subcommand() {
+        let mut page = Vec::new();
+        handler(&mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with(".TH MAGG 1 "));
+        assert!(page.contains("\n.SS find\\-many\n.nf\n"));
+        assert!(page.contains("\\-\\-pipeline\\-name"));
+        assert!(!page.contains(".SS complete\n"));
+    }
+}
@@ -1,82 +1,268 @@
-use crate::shared::{keywords, Config, MongoDbCommand};
+use clap::{CommandFactory, Parser};
+
+use crate::shared::{Config, GlobalArgs};
 
 mod aggregate;
+mod browse;
+mod bulk_write;
+mod completions;
+mod copy;
 mod count;
 mod create;
+mod current_op;
+mod db_stats;
 mod delete_many;
 mod delete_one;
+mod dump;
+mod edit;
 mod find_many;
 mod find_one;
+mod history;
+mod kill_op;
 mod list_databases;
+mod man;
+mod profiler;
+mod restore;
+mod schema;
+mod server_status;
+mod shell;
+mod stats;
+mod transaction;
+mod validator;
+mod watch;
 
-pub fn main_app() -> clap::App<'static, 'static> {
-    clap::App::new(clap::crate_name!())
-        .version(clap::crate_version!())
-        .author(clap::crate_authors!())
-        .about(clap::crate_description!())
-        .subcommand(aggregate::aggregate_app())
-        .subcommand(create::create_app())
-        .subcommand(find_many::find_many_app())
-        .subcommand(find_one::find_one_app())
-        .subcommand(count::count_app())
-        .subcommand(delete_many::delete_many_app())
-        .subcommand(delete_one::delete_one_app())
-        .subcommand(list_databases::list_databases_app())
-        .arg(
-            clap::Arg::with_name(keywords::CONNECTION_URI)
-                .long(keywords::CONNECTION_URI)
-                .required(false)
-                .multiple(false)
-                .takes_value(true),
-        )
-        .arg(
-            clap::Arg::with_name(keywords::COLLECTION_NAME)
-                .long(keywords::COLLECTION_NAME)
-                .required(false)
-                .takes_value(true),
-        )
-        .arg(
-            clap::Arg::with_name(keywords::DATABASE_NAME)
-                .long(keywords::DATABASE_NAME)
-                .required(false)
-                .takes_value(true),
-        )
-        .arg(
-            clap::Arg::with_name(keywords::CONFIG_FILE)
-                .long(keywords::CONFIG_FILE)
-                .required(false)
-                .takes_value(true),
-        )
+#[derive(Parser, Debug)]
+#[command(name = "magg", author, version, about)]
+pub struct Magg {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The subcommands, which are also the commands accepted by the shell.
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    Aggregate(aggregate::AggregateArgs),
+    Create(create::CreateArgs),
+    FindMany(find_many::FindManyArgs),
+    FindOne(find_one::FindOneArgs),
+    Count(count::CountArgs),
+    DeleteMany(delete_many::DeleteManyArgs),
+    DeleteOne(delete_one::DeleteOneArgs),
+    /// List the databases with their size on disk
+    ListDatabases,
+    BulkWrite(bulk_write::BulkWriteArgs),
+    Transaction(transaction::TransactionArgs),
+    Watch(watch::WatchArgs),
+    Dump(dump::DumpArgs),
+    Restore(restore::RestoreArgs),
+    Copy(copy::CopyArgs),
+    Schema(schema::SchemaArgs),
+    Validator(validator::ValidatorArgs),
+    Shell(shell::ShellArgs),
+    Browse(browse::BrowseArgs),
+    Edit(edit::EditArgs),
+    History(history::HistoryArgs),
+    /// Print the version, uptime, connections, operation counters and memory usage of the
+    /// server. The JSON output has the numbers as reported by the server, e.g. the memory in MiB
+    ServerStatus,
+    /// Print the number of documents, the average document size, the storage size and the
+    /// index sizes of the collection
+    Stats,
+    /// Print the number of collections, views, documents and indexes of the database and the
+    /// space they use
+    DbStats,
+    Profiler(profiler::ProfilerArgs),
+    CurrentOp(current_op::CurrentOpArgs),
+    KillOp(kill_op::KillOpArgs),
+    Completions(completions::CompletionsArgs),
+    #[command(hide = true)]
+    Complete(completions::CompleteArgs),
+    /// Print the man page in roff, e.g. 'magg man > /usr/local/share/man/man1/magg.1'
+    Man,
+}
+
+pub fn main_app() -> clap::Command {
+    Magg::command()
+}
+
+/// Run the subcommands that need no configuration, i.e. the ones describing the command line
+/// itself and the history. Returns None for the other subcommands.
+pub fn to_standalone_handler(
+    magg: &Magg,
+    out: &mut dyn std::io::Write,
+) -> Option<Result<(), Box<dyn std::error::Error>>> {
+    match &magg.command {
+        Some(Command::Completions(args)) => Some(completions::handler(args, out)),
+        Some(Command::Complete(args)) => {
+            Some(completions::complete_handler(args, &magg.global, out))
+        }
+        Some(Command::Man) => Some(man::handler(out)),
+        Some(Command::History(args)) => {
+            Some(history::handler(args, Config::from_args(&magg.global), out))
+        }
+        _ => None,
+    }
 }
 
 pub fn to_handler(
-    input: clap::ArgMatches,
+    magg: Magg,
     config: Config,
+    out: &mut dyn std::io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(matches) = input.subcommand_matches(MongoDbCommand::Aggregate.to_str()) {
-        aggregate::handler(matches, config)?;
-    } else if let Some(matches) = input.subcommand_matches(MongoDbCommand::Create.to_str()) {
-        create::handler(matches, config)?;
-    } else if let Some(matches) = input.subcommand_matches(MongoDbCommand::FindMany.to_str()) {
-        find_many::handler(matches, config)?;
-    } else if let Some(matches) = input.subcommand_matches(MongoDbCommand::FindOne.to_str()) {
-        find_one::handler(matches, config)?;
-    } else if let Some(matches) = input.subcommand_matches(MongoDbCommand::DeleteOne.to_str()) {
-        delete_one::handler(matches, config)?
-    } else if let Some(matches) = input.subcommand_matches(MongoDbCommand::DeleteMany.to_str()) {
-        delete_many::handler(matches, config)?;
-    } else if let Some(matches) = input.subcommand_matches(MongoDbCommand::Count.to_str()) {
-        count::handler(matches, config)?;
-    } else if let Some(matches) = input.subcommand_matches(MongoDbCommand::ListDatabases.to_str()) {
-        list_databases::handler(matches, config)?;
-    } else if let Some(subcommand) = input.subcommand_name() {
-        return Err(format!(
-            "There are no subcommand '{}'. Please see --help",
-            subcommand
+    // In the shell the history re-runs commands with the configuration of the shell.
+    if let Some(Command::History(args)) = &magg.command {
+        return history::handler(args, Ok(config), out);
+    }
+    if let Some(result) = to_standalone_handler(&magg, out) {
+        return result;
+    }
+    let output_format = magg.global.output_format;
+    match magg.command {
+        Some(Command::Aggregate(args)) => aggregate::handler(&args, output_format, config, out),
+        Some(Command::Create(args)) => create::handler(&args, config, out),
+        Some(Command::FindMany(args)) => find_many::handler(&args, output_format, config, out),
+        Some(Command::FindOne(args)) => find_one::handler(&args, output_format, config, out),
+        Some(Command::Count(args)) => count::handler(&args, config, out),
+        Some(Command::DeleteMany(args)) => delete_many::handler(&args, config, out),
+        Some(Command::DeleteOne(args)) => delete_one::handler(&args, config, out),
+        Some(Command::ListDatabases) => list_databases::handler(output_format, config, out),
+        Some(Command::BulkWrite(args)) => bulk_write::handler(&args, config, out),
+        Some(Command::Transaction(args)) => transaction::handler(&args, config, out),
+        Some(Command::Watch(args)) => watch::handler(&args, output_format, config, out),
+        Some(Command::Dump(args)) => dump::handler(&args, config, out),
+        Some(Command::Restore(args)) => restore::handler(&args, config, out),
+        Some(Command::Copy(args)) => copy::handler(&args, config, out),
+        Some(Command::Schema(args)) => schema::handler(&args, config, out),
+        Some(Command::Validator(args)) => validator::handler(&args, config, out),
+        Some(Command::Shell(args)) => shell::handler(&args, config, out),
+        Some(Command::Browse(args)) => browse::handler(&args, config, out),
+        Some(Command::Edit(args)) => edit::handler(&args, config, out),
+        Some(Command::ServerStatus) => server_status::handler(output_format, config, out),
+        Some(Command::Stats) => stats::handler(output_format, config, out),
+        Some(Command::DbStats) => db_stats::handler(output_format, config, out),
+        Some(Command::Profiler(args)) => profiler::handler(&args, output_format, config, out),
+        Some(Command::CurrentOp(args)) => current_op::handler(&args, output_format, config, out),
+        Some(Command::KillOp(args)) => kill_op::handler(&args, config, out),
+        // Handled by `to_standalone_handler`.
+        Some(Command::Completions(_))
+        | Some(Command::Complete(_))
+        | Some(Command::Man)
+        | Some(Command::History(_)) => Ok(()),
+        None => {
+            let mut out = out;
+            main_app().write_long_help(&mut out)?;
+            Ok(())
+        }
+    }
+}
+
+/// Parse the arguments like the binary does and run the subcommand against the in-memory
+/// backend. Returns what the subcommand wrote.
+#[cfg(test)]
+pub(crate) fn run_in_memory(
+    backend: &crate::backend::MemoryBackend,
+    config: &Config,
+    args: &[&str],
+) -> Result<String, Box<dyn std::error::Error>> {
+    use clap::FromArgMatches;
+
+    let matches = main_app()
+        .try_get_matches_from(std::iter::once(clap::crate_name!()).chain(args.iter().copied()))?;
+    let magg = Magg::from_arg_matches(&matches)?;
+    let output_format = magg.global.output_format;
+    let mut out = vec![];
+    match magg.command {
+        Some(Command::Aggregate(args)) => {
+            aggregate::run(&args, output_format, config, backend, &mut out)?
+        }
+        Some(Command::Create(args)) => create::run(&args, config, backend, &mut out)?,
+        Some(Command::FindMany(args)) => {
+            find_many::run(&args, output_format, config, backend, &mut out)?
+        }
+        Some(Command::FindOne(args)) => {
+            find_one::run(&args, output_format, config, backend, &mut out)?
+        }
+        Some(Command::Count(args)) => count::run(&args, config, backend, &mut out)?,
+        Some(Command::DeleteMany(args)) => delete_many::run(&args, config, backend, &mut out)?,
+        Some(Command::DeleteOne(args)) => delete_one::run(&args, config, backend, &mut out)?,
+        Some(Command::ListDatabases) => {
+            list_databases::run(output_format, config, backend, &mut out)?
+        }
+        Some(_) => {
+            return Err(format!(
+                "'{}' cannot run against the in-memory backend",
+                matches.subcommand_name().unwrap_or_default()
+            )
+            .into())
+        }
+        None => return Err("There is no subcommand".into()),
+    }
+    Ok(String::from_utf8(out)?)
+}
+
+/// The `test.items` collection the in-memory tests run against.
+#[cfg(test)]
+pub(crate) fn seeded_backend() -> crate::backend::MemoryBackend {
+    use crate::backend::Backend;
+    use mongodb::bson::doc;
+
+    let backend = crate::backend::MemoryBackend::new();
+    backend
+        .insert(
+            "test",
+            "items",
+            vec![
+                doc! { "_id": 1, "name": "apple", "price": 3.5, "tags": ["fruit", "red"] },
+                doc! { "_id": 2, "name": "bread", "price": 2, "stock": { "count": 0 } },
+                doc! { "_id": 3, "name": "cherry", "price": 10_i64, "tags": ["fruit"] },
+                doc! { "_id": 4, "name": "Dates", "stock": { "count": 12 } },
+            ],
         )
-        .into());
-    } else {
-        main_app().print_long_help()?;
+        .unwrap();
+    backend
+}
+
+/// A configuration for [`seeded_backend`] with a saved query, a fragment and a saved pipeline.
+#[cfg(test)]
+pub(crate) fn test_config() -> Config {
+    use crate::shared::{Pipeline, PipelineDescription, Query};
+    use mongodb::bson::doc;
+
+    let mut config = Config::new("mongodb://localhost", "test", "items");
+    config.queries.push(Query {
+        name: "expensive".to_string(),
+        description: PipelineDescription::OneLine("Most expensive first".to_string()),
+        filter: doc! { "price": { "$gt": 3 } },
+        projection: Some(doc! { "name": 1 }),
+        sort: Some(doc! { "price": -1 }),
+        limit: None,
+    });
+    config.fragments.insert(
+        "fruit".to_string(),
+        vec![doc! { "$match": { "tags": "fruit" } }],
+    );
+    config.pipelines.push(Pipeline {
+        name: "fruit-count".to_string(),
+        description: PipelineDescription::OneLine("Count the fruit".to_string()),
+        stages: vec![doc! { "$include": "fruit" }, doc! { "$count": "fruit" }],
+        stages_file: None,
+        options: Default::default(),
+        collection_name: None,
+        database_level: false,
+    });
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::Magg;
+
+    #[test]
+    fn declares_consistent_arguments() {
+        Magg::command().debug_assert();
     }
-    Ok(())
 }
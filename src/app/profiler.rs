@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+
+use prettytable::{Cell, Row, Table};
+
+use crate::shared::{connect, get_number, match_wildcards, Config, OutputFormat};
+
+/// The fields of a profiled command that do not change what it does.
+const IGNORED_FIELDS: &[&str] = &[
+    "lsid",
+    "txnNumber",
+    "autocommit",
+    "startTransaction",
+    "readConcern",
+    "writeConcern",
+    "comment",
+    "maxTimeMS",
+    "cursor",
+    "batchSize",
+    "apiVersion",
+    "apiStrict",
+    "apiDeprecationErrors",
+];
+
+/// Manage the database profiler and look at the operations it recorded
+#[derive(clap::Args, Debug)]
+#[command(arg_required_else_help = true)]
+pub struct ProfilerArgs {
+    #[command(subcommand)]
+    command: ProfilerCommand,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ProfilerCommand {
+    /// Print the profiling level, the slow operation threshold and sample rate
+    Get,
+    Set(SetArgs),
+    Slowest(SlowestArgs),
+}
+
+/// Change the profiling level or the slow operation threshold
+#[derive(clap::Args, Debug)]
+struct SetArgs {
+    /// 0 turns the profiler off, 1 profiles the operations slower than the threshold and 2
+    /// profiles all of them
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(i32).range(0..=2),
+        required_unless_present_any = ["slow_ms", "sample_rate"]
+    )]
+    level: Option<i32>,
+    /// The threshold in milliseconds above which operations are slow
+    #[arg(long)]
+    slow_ms: Option<i32>,
+    /// The fraction of the slow operations that are profiled
+    #[arg(long)]
+    sample_rate: Option<f64>,
+}
+
+/// Print the slowest operations recorded in system.profile, grouped by the shape of their
+/// command, i.e. the command with its values left out
+#[derive(clap::Args, Debug)]
+struct SlowestArgs {
+    /// Only the operations on the matching namespaces, e.g. 'shop.*'
+    #[arg(long)]
+    namespace: Option<String>,
+    /// The number of shapes printed
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+}
+
+/// The command with every value replaced by "?", so that the same query with different values
+/// has the same shape.
+fn query_shape(command: &mongodb::bson::Document) -> mongodb::bson::Document {
+    command
+        .iter()
+        .filter(|(key, _)| !key.starts_with('$') && !IGNORED_FIELDS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value_shape(value)))
+        .collect()
+}
+
+fn value_shape(value: &mongodb::bson::Bson) -> mongodb::bson::Bson {
+    match value {
+        mongodb::bson::Bson::Document(document) => mongodb::bson::Bson::Document(
+            document
+                .iter()
+                .map(|(key, value)| (key.clone(), value_shape(value)))
+                .collect(),
+        ),
+        // e.g. the stages of a pipeline or the clauses of an $or. Arrays of values, like the
+        // operand of $in, have the same shape whatever their length.
+        mongodb::bson::Bson::Array(values)
+            if values
+                .iter()
+                .all(|value| matches!(value, mongodb::bson::Bson::Document(_))) =>
+        {
+            let mut shapes = Vec::<mongodb::bson::Bson>::new();
+            for shape in values.iter().map(value_shape) {
+                // The documents of an insert usually have the same shape.
+                if !shapes.contains(&shape) {
+                    shapes.push(shape);
+                }
+            }
+            mongodb::bson::Bson::Array(shapes)
+        }
+        _ => mongodb::bson::Bson::String("?".to_string()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct ShapeGroup {
+    namespace: String,
+    operation: String,
+    shape: mongodb::bson::Document,
+    count: u64,
+    total_millis: f64,
+    max_millis: f64,
+}
+
+impl ShapeGroup {
+    fn to_document(&self) -> mongodb::bson::Document {
+        mongodb::bson::doc! {
+            "ns": &self.namespace,
+            "op": &self.operation,
+            "shape": self.shape.clone(),
+            "count": self.count as i64,
+            "totalMillis": self.total_millis,
+            "avgMillis": self.total_millis / self.count as f64,
+            "maxMillis": self.max_millis,
+        }
+    }
+}
+
+/// Group the entries of `system.profile` by namespace, operation and shape, slowest first.
+fn group_by_shape(entries: Vec<mongodb::bson::Document>) -> Vec<ShapeGroup> {
+    let mut groups = Vec::<ShapeGroup>::new();
+    let mut indexes = HashMap::<String, usize>::new();
+    for entry in entries {
+        let group = ShapeGroup {
+            namespace: entry.get_str("ns").unwrap_or_default().to_string(),
+            operation: entry.get_str("op").unwrap_or_default().to_string(),
+            shape: query_shape(&entry.get_document("command").cloned().unwrap_or_default()),
+            count: 1,
+            total_millis: get_number(&entry, "millis").unwrap_or_default(),
+            max_millis: get_number(&entry, "millis").unwrap_or_default(),
+        };
+        let key = format!(
+            "{} {} {}",
+            group.namespace,
+            group.operation,
+            mongodb::bson::Bson::Document(group.shape.clone()).into_relaxed_extjson()
+        );
+        match indexes.get(&key) {
+            Some(&index) => {
+                let existing = &mut groups[index];
+                existing.count += 1;
+                existing.total_millis += group.total_millis;
+                existing.max_millis = existing.max_millis.max(group.max_millis);
+            }
+            None => {
+                indexes.insert(key, groups.len());
+                groups.push(group);
+            }
+        }
+    }
+    groups.sort_by(|a, b| {
+        b.max_millis
+            .partial_cmp(&a.max_millis)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.count.cmp(&a.count))
+    });
+    groups
+}
+
+/// A field of the reply to the profile command, which servers and mongos do not always report.
+fn reported(status: &mongodb::bson::Document, key: &str, unit: &str) -> String {
+    match get_number(status, key) {
+        Some(value) => format!("{}{}", value, unit),
+        None => "not reported".to_string(),
+    }
+}
+
+fn write_status(
+    status: &mongodb::bson::Document,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    writeln!(out, "Profiling level: {}", reported(status, "was", ""))?;
+    writeln!(
+        out,
+        "Slow operation threshold: {}",
+        reported(status, "slowms", " ms")
+    )?;
+    if let Some(sample_rate) = get_number(status, "sampleRate") {
+        writeln!(out, "Sample rate: {}", sample_rate)?;
+    }
+    Ok(())
+}
+
+fn write_previous_status(
+    database_name: &str,
+    previous: &mongodb::bson::Document,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    writeln!(
+        out,
+        "Updated the profiler of {}. Its previous level was {} and its threshold {}",
+        database_name,
+        reported(previous, "was", ""),
+        reported(previous, "slowms", " ms")
+    )?;
+    Ok(())
+}
+
+fn get(
+    database: &mongodb::sync::Database,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let status = database.run_command(mongodb::bson::doc! { "profile": -1 }, None)?;
+    write_status(&status, out)
+}
+
+fn set(
+    args: &SetArgs,
+    database: &mongodb::sync::Database,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // -1 keeps the current level.
+    let mut command = mongodb::bson::doc! { "profile": args.level.unwrap_or(-1) };
+    if let Some(slow_ms) = args.slow_ms {
+        command.insert("slowms", slow_ms);
+    }
+    if let Some(sample_rate) = args.sample_rate {
+        command.insert("sampleRate", sample_rate);
+    }
+    let previous = database.run_command(command, None)?;
+    write_previous_status(database.name(), &previous, out)?;
+    get(database, out)
+}
+
+fn slowest(
+    args: &SlowestArgs,
+    database: &mongodb::sync::Database,
+    output_format: OutputFormat,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let limit = args.limit;
+    let namespace = args.namespace.as_deref();
+    let options = mongodb::options::FindOptions::builder()
+        .projection(mongodb::bson::doc! { "ns": 1, "op": 1, "command": 1, "millis": 1 })
+        .build();
+    let entries = database
+        .collection::<mongodb::bson::Document>("system.profile")
+        .find(None, options)?
+        .filter(|result| match (result, namespace) {
+            (Ok(entry), Some(namespace)) => {
+                match_wildcards(namespace, entry.get_str("ns").unwrap_or_default()).is_some()
+            }
+            _ => true,
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if entries.is_empty() {
+        writeln!(
+            out,
+            "The profiler has not recorded any operation. Turn it on with 'profiler set --level 1'"
+        )?;
+        return Ok(());
+    }
+
+    let groups = group_by_shape(entries);
+    if output_format != OutputFormat::Document {
+        for group in groups.iter().take(limit) {
+            writeln!(
+                out,
+                "{}",
+                output_format.format_document(&group.to_document())
+            )?;
+        }
+        return Ok(());
+    }
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Namespace"),
+        Cell::new("Operation"),
+        Cell::new("Count"),
+        Cell::new("Avg ms"),
+        Cell::new("Max ms"),
+        Cell::new("Shape"),
+    ]));
+    for group in groups.iter().take(limit) {
+        table.add_row(Row::new(vec![
+            Cell::new(&group.namespace),
+            Cell::new(&group.operation),
+            Cell::new(&group.count.to_string()),
+            Cell::new(&format!("{:.1}", group.total_millis / group.count as f64)),
+            Cell::new(&group.max_millis.to_string()),
+            Cell::new(
+                &mongodb::bson::Bson::Document(group.shape.clone())
+                    .into_relaxed_extjson()
+                    .to_string(),
+            ),
+        ]));
+    }
+    table.print(out)?;
+    let operations = groups.iter().map(|group| group.count).sum::<u64>();
+    writeln!(
+        out,
+        "{} shape{} in {} operation{}",
+        groups.len(),
+        if groups.len() == 1 { "" } else { "s" },
+        operations,
+        if operations == 1 { "" } else { "s" }
+    )?;
+    Ok(())
+}
+
+pub fn handler(
+    args: &ProfilerArgs,
+    output_format: OutputFormat,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let database = client.database(&config.database_name);
+    match &args.command {
+        ProfilerCommand::Get => get(&database, out),
+        ProfilerCommand::Set(args) => set(args, &database, out),
+        ProfilerCommand::Slowest(args) => slowest(args, &database, output_format, out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn leaves_the_values_out_of_the_shape() {
+        assert_eq!(
+            query_shape(&doc! {
+                "find": "items",
+                "filter": { "price": { "$in": [1, 2, 3] }, "$or": [{ "a": 1 }, { "b": 2 }] },
+                "limit": 5,
+                "lsid": { "id": 1 },
+                "$db": "shop",
+            }),
+            doc! {
+                "find": "?",
+                "filter": { "price": { "$in": "?" }, "$or": [{ "a": "?" }, { "b": "?" }] },
+                "limit": "?",
+            }
+        );
+        assert_eq!(
+            query_shape(&doc! { "insert": "items", "documents": [{ "a": 1 }, { "a": 2 }] }),
+            doc! { "insert": "?", "documents": [{ "a": "?" }] }
+        );
+    }
+
+    #[test]
+    fn groups_the_operations_by_shape() {
+        let entry = |price: i32, millis: i32| {
+            doc! {
+                "op": "query",
+                "ns": "shop.items",
+                "command": { "find": "items", "filter": { "price": price } },
+                "millis": millis,
+            }
+        };
+        let groups = group_by_shape(vec![
+            entry(1, 10),
+            doc! { "op": "insert", "ns": "shop.items", "command": { "insert": "items" }, "millis": 50 },
+            entry(2, 30),
+        ]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].operation, "insert");
+        assert_eq!(
+            groups[1].to_document(),
+            doc! {
+                "ns": "shop.items",
+                "op": "query",
+                "shape": { "find": "?", "filter": { "price": "?" } },
+                "count": 2_i64,
+                "totalMillis": 40.0,
+                "avgMillis": 20.0,
+                "maxMillis": 30.0,
+            }
+        );
+    }
+
+    #[test]
+    fn prints_the_status_reported_by_the_server() {
+        let mut out = vec![];
+        write_status(
+            &doc! { "was": 1, "slowms": 100, "sampleRate": 0.5, "ok": 1.0 },
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Profiling level: 1\nSlow operation threshold: 100 ms\nSample rate: 0.5\n"
+        );
+    }
+
+    #[test]
+    fn prints_the_missing_fields_as_not_reported() {
+        let mut out = vec![];
+        write_status(&doc! { "ok": 1.0 }, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Profiling level: not reported\nSlow operation threshold: not reported\n"
+        );
+        let mut out = vec![];
+        write_previous_status("shop", &doc! { "was": 0, "ok": 1.0 }, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Updated the profiler of shop. Its previous level was 0 and its threshold not reported\n"
+        );
+    }
+}
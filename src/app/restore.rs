@@ -0,0 +1,190 @@
+use std::convert::TryFrom;
+use std::io::BufRead;
+
+use crate::shared::{connect, create_index_specs, match_wildcards, Config};
+
+/// Recreate collections, their options, indexes and documents from a dump
+#[derive(clap::Args, Debug)]
+pub struct RestoreArgs {
+    /// The directory created by 'dump'
+    #[arg(long, default_value = "dump")]
+    directory: String,
+    /// Drop each collection before restoring it
+    #[arg(long)]
+    drop: bool,
+    /// Only restore the namespaces matching this pattern, e.g. 'database.*'. '*' matches any
+    /// sequence of characters
+    #[arg(long, default_value = "*")]
+    ns_include: String,
+    /// Rename the namespaces matching this pattern, e.g. 'prod.*'. See --ns-to
+    #[arg(long, requires = "ns_to")]
+    ns_from: Option<String>,
+    /// The new name of the namespaces matching --ns-from, e.g. 'staging.*'. Each '*' is
+    /// replaced by what the corresponding '*' in --ns-from matched
+    #[arg(long, requires = "ns_from")]
+    ns_to: Option<String>,
+    /// Number of documents inserted per command
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+}
+
+fn rename_namespace(from: &str, to: &str, namespace: &str) -> String {
+    match match_wildcards(from, namespace) {
+        Some(captures) => {
+            let mut captures = captures.into_iter();
+            let mut parts = to.split('*');
+            let mut renamed = parts.next().unwrap_or_default().to_string();
+            for part in parts {
+                renamed.push_str(&captures.next().unwrap_or_default());
+                renamed.push_str(part);
+            }
+            renamed
+        }
+        None => namespace.to_string(),
+    }
+}
+
+fn is_namespace_exists_error(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        mongodb::error::ErrorKind::Command(mongodb::error::CommandError { code: 48, .. })
+    )
+}
+
+fn read_metadata(
+    path: &std::path::Path,
+) -> Result<Option<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    match serde_json::from_slice::<serde_json::Value>(&std::fs::read(path)?)? {
+        serde_json::Value::Object(o) => Ok(Some(mongodb::bson::Document::try_from(o)?)),
+        _ => Err(format!("{} must contain an object", path.display()).into()),
+    }
+}
+
+fn restore_collection(
+    client: &mongodb::sync::Client,
+    namespace: &str,
+    bson_path: &std::path::Path,
+    metadata: Option<mongodb::bson::Document>,
+    drop: bool,
+    batch_size: usize,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (database_name, collection_name) = namespace
+        .split_once('.')
+        .ok_or_else(|| format!("Invalid namespace '{}'", namespace))?;
+    let database = client.database(database_name);
+    let collection = database.collection::<mongodb::bson::Document>(collection_name);
+    if drop {
+        collection.drop(None)?;
+    }
+
+    let mut index_count = 0;
+    if let Some(metadata) = metadata {
+        let mut create = mongodb::bson::doc! { "create": collection_name };
+        if let Ok(options) = metadata.get_document("options") {
+            create.extend(options.clone());
+        }
+        match database.run_command(create, None) {
+            Err(e) if is_namespace_exists_error(&e) => {}
+            o => {
+                o?;
+            }
+        }
+        let indexes = metadata
+            .get_array("indexes")
+            .map(|indexes| {
+                indexes
+                    .iter()
+                    .filter_map(|i| i.as_document().cloned())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        index_count = create_index_specs(&database, collection_name, &indexes)?;
+    }
+
+    let mut reader = std::io::BufReader::new(std::fs::File::open(bson_path)?);
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut count = 0;
+    while !reader.fill_buf()?.is_empty() {
+        batch.push(mongodb::bson::Document::from_reader(&mut reader)?);
+        if batch.len() == batch_size {
+            count += batch.len();
+            collection.insert_many(batch.drain(..), None)?;
+        }
+    }
+    if !batch.is_empty() {
+        count += batch.len();
+        collection.insert_many(batch, None)?;
+    }
+    writeln!(
+        out,
+        "Restored {} document{} and {} index{} into {} from {}",
+        count,
+        if count == 1 { "" } else { "s" },
+        index_count,
+        if index_count == 1 { "" } else { "es" },
+        namespace,
+        bson_path.display()
+    )?;
+    Ok(())
+}
+
+pub fn handler(
+    args: &RestoreArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let directory = std::path::Path::new(&args.directory);
+    let ns_include = args.ns_include.as_str();
+    let drop = args.drop;
+    let batch_size = args.batch_size as usize;
+
+    let mut restored = 0;
+    for database_entry in std::fs::read_dir(directory)? {
+        let database_path = database_entry?.path();
+        if !database_path.is_dir() {
+            continue;
+        }
+        let database_name = database_path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for collection_entry in std::fs::read_dir(&database_path)? {
+            let bson_path = collection_entry?.path();
+            if bson_path.extension().and_then(|s| s.to_str()) != Some("bson") {
+                continue;
+            }
+            let collection_name = bson_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let namespace = format!("{}.{}", database_name, collection_name);
+            if match_wildcards(ns_include, &namespace).is_none() {
+                continue;
+            }
+            let target = match (&args.ns_from, &args.ns_to) {
+                (Some(from), Some(to)) => rename_namespace(from, to, &namespace),
+                _ => namespace,
+            };
+            let metadata =
+                read_metadata(&database_path.join(format!("{}.metadata.json", collection_name)))?;
+            restore_collection(
+                &client, &target, &bson_path, metadata, drop, batch_size, out,
+            )?;
+            restored += 1;
+        }
+    }
+    if restored == 0 {
+        return Err(format!(
+            "No collection in '{}' matches '{}'",
+            directory.display(),
+            ns_include
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+
+use prettytable::{Cell, Row, Table};
+
+use crate::shared::{bson_as_i64, bson_type_name, connect, stringify_bson, Config};
+
+const MAX_EXAMPLES: usize = 3;
+const MAX_EXAMPLE_LENGTH: usize = 40;
+
+/// Describe the fields and types found in the documents of the collection
+#[derive(clap::Args, Debug)]
+pub struct SchemaArgs {
+    /// Only analyze N randomly sampled documents. Analyzes the whole collection if not given
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    sample_size: Option<i64>,
+    /// Print a $jsonSchema validator describing the documents instead of a table
+    #[arg(long)]
+    json_schema: bool,
+}
+
+/// What was observed at a single field path.
+#[derive(Default, Debug)]
+struct FieldNode {
+    /// Number of times the field was present.
+    count: usize,
+    types: BTreeMap<&'static str, usize>,
+    examples: Vec<String>,
+    min_number: Option<f64>,
+    max_number: Option<f64>,
+    min_date: Option<mongodb::bson::DateTime>,
+    max_date: Option<mongodb::bson::DateTime>,
+    /// The fields of the embedded documents found at this path.
+    children: BTreeMap<String, FieldNode>,
+    /// The elements of the arrays found at this path.
+    items: Option<Box<FieldNode>>,
+}
+
+impl FieldNode {
+    fn observe(&mut self, value: &mongodb::bson::Bson) {
+        self.count += 1;
+        *self.types.entry(bson_type_name(value)).or_insert(0) += 1;
+        match value {
+            mongodb::bson::Bson::Document(document) => self.observe_document(document),
+            mongodb::bson::Bson::Array(array) => {
+                let items = self.items.get_or_insert_with(Default::default);
+                array.iter().for_each(|element| items.observe(element));
+            }
+            mongodb::bson::Bson::Double(_)
+            | mongodb::bson::Bson::Int32(_)
+            | mongodb::bson::Bson::Int64(_) => {
+                if let Some(number) = value
+                    .as_f64()
+                    .or_else(|| bson_as_i64(value).map(|i| i as f64))
+                {
+                    self.min_number = Some(self.min_number.map_or(number, |m| m.min(number)));
+                    self.max_number = Some(self.max_number.map_or(number, |m| m.max(number)));
+                }
+                self.add_example(value);
+            }
+            mongodb::bson::Bson::DateTime(date) => {
+                self.min_date = Some(self.min_date.map_or(*date, |m| m.min(*date)));
+                self.max_date = Some(self.max_date.map_or(*date, |m| m.max(*date)));
+                self.add_example(value);
+            }
+            o => self.add_example(o),
+        }
+    }
+
+    fn observe_document(&mut self, document: &mongodb::bson::Document) {
+        for (key, value) in document {
+            self.children.entry(key.clone()).or_default().observe(value);
+        }
+    }
+
+    fn add_example(&mut self, value: &mongodb::bson::Bson) {
+        if self.examples.len() >= MAX_EXAMPLES {
+            return;
+        }
+        let mut example = stringify_bson(value).to_string();
+        if example.chars().count() > MAX_EXAMPLE_LENGTH {
+            example = example.chars().take(MAX_EXAMPLE_LENGTH).collect::<String>() + "...";
+        }
+        if !self.examples.contains(&example) {
+            self.examples.push(example);
+        }
+    }
+
+    fn range(&self) -> (String, String) {
+        match (
+            self.min_number,
+            self.max_number,
+            self.min_date,
+            self.max_date,
+        ) {
+            (Some(min), Some(max), _, _) => (min.to_string(), max.to_string()),
+            (_, _, Some(min), Some(max)) => {
+                (min.to_chrono().to_rfc3339(), max.to_chrono().to_rfc3339())
+            }
+            _ => (String::new(), String::new()),
+        }
+    }
+
+    /// Number of times this path held an embedded document.
+    fn object_count(&self) -> usize {
+        self.types.get("object").copied().unwrap_or(0)
+    }
+
+    fn add_rows(&self, path: &str, parent_count: Option<usize>, table: &mut Table) {
+        let (min, max) = self.range();
+        let presence = match parent_count {
+            Some(parent_count) if parent_count > 0 => {
+                format!("{:.1}%", self.count as f64 * 100.0 / parent_count as f64)
+            }
+            _ => "-".to_string(),
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(path),
+            Cell::new(
+                &self
+                    .types
+                    .iter()
+                    .map(|(name, count)| format!("{} ({})", name, count))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            Cell::new(&presence),
+            Cell::new(&self.examples.join("\n")),
+            Cell::new(&min),
+            Cell::new(&max),
+        ]));
+        self.add_children_rows(path, table);
+    }
+
+    fn add_children_rows(&self, path: &str, table: &mut Table) {
+        for (key, child) in self.children.iter() {
+            let child_path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+            child.add_rows(&child_path, Some(self.object_count()), table);
+        }
+        if let Some(items) = &self.items {
+            items.add_rows(&format!("{}[]", path), None, table);
+        }
+    }
+
+    /// Describe the values seen at this path as a `$jsonSchema` schema.
+    /// Fields present in every embedded document are marked as required. Without any value,
+    /// e.g. for the items of arrays that were always empty, any type is accepted.
+    fn to_json_schema(&self) -> mongodb::bson::Document {
+        let mut schema = mongodb::bson::Document::new();
+        let types = self.types.keys().copied().collect::<Vec<_>>();
+        match types.as_slice() {
+            [] => {}
+            [single] => {
+                schema.insert("bsonType", *single);
+            }
+            _ => {
+                schema.insert("bsonType", types);
+            }
+        }
+        if !self.children.is_empty() {
+            let object_count = self.object_count();
+            let required = self
+                .children
+                .iter()
+                .filter(|(_, child)| child.count == object_count)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            if !required.is_empty() {
+                schema.insert("required", required);
+            }
+            schema.insert(
+                "properties",
+                self.children
+                    .iter()
+                    .map(|(key, child)| (key.clone(), child.to_json_schema().into()))
+                    .collect::<mongodb::bson::Document>(),
+            );
+        }
+        if let Some(items) = &self.items {
+            schema.insert("items", items.to_json_schema());
+        }
+        schema
+    }
+}
+
+pub fn handler(
+    args: &SchemaArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let database = client.database(&config.database_name);
+    let collection =
+        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
+    let cursor = match args.sample_size {
+        Some(size) => collection.aggregate(
+            vec![mongodb::bson::doc! { "$sample": { "size": size } }],
+            None,
+        )?,
+        None => collection.find(None, None)?,
+    };
+
+    let mut root = FieldNode::default();
+    for result in cursor {
+        root.observe(&mongodb::bson::Bson::Document(result?));
+    }
+
+    if args.json_schema {
+        if root.count == 0 {
+            return Err("No documents were sampled to describe".into());
+        }
+        let validator = mongodb::bson::doc! { "$jsonSchema": root.to_json_schema() };
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string_pretty(
+                &mongodb::bson::Bson::Document(validator).into_relaxed_extjson()
+            )?
+        )?;
+    } else {
+        let mut table = Table::new();
+        table.add_row(Row::new(vec![
+            Cell::new("Path"),
+            Cell::new("Types"),
+            Cell::new("Presence"),
+            Cell::new("Examples"),
+            Cell::new("Min"),
+            Cell::new("Max"),
+        ]));
+        root.add_children_rows("", &mut table);
+        table.print(out)?;
+        writeln!(
+            out,
+            "Analyzed {} document{}",
+            root.count,
+            if root.count == 1 { "" } else { "s" }
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    fn json_schema(documents: Vec<mongodb::bson::Document>) -> mongodb::bson::Document {
+        let mut root = FieldNode::default();
+        for document in documents {
+            root.observe(&mongodb::bson::Bson::Document(document));
+        }
+        root.to_json_schema()
+    }
+
+    #[test]
+    fn describes_the_sampled_documents() {
+        assert_eq!(
+            json_schema(vec![doc! { "a": 1, "b": "x" }, doc! { "a": 1.5 }]),
+            doc! {
+                "bsonType": "object",
+                "required": ["a"],
+                "properties": {
+                    "a": { "bsonType": ["double", "int"] },
+                    "b": { "bsonType": "string" },
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn accepts_any_type_without_values() {
+        assert_eq!(json_schema(vec![]), doc! {});
+        assert_eq!(
+            json_schema(vec![doc! { "a": [] }]),
+            doc! {
+                "bsonType": "object",
+                "required": ["a"],
+                "properties": { "a": { "bsonType": "array", "items": {} } },
+            }
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::shared::{connect, format_size, get_number, print_fields, Config, OutputFormat};
+
+/// The uptime in days, hours, minutes and seconds, e.g. "2d 3h 0m 12s".
+fn format_uptime(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h {}m {}s", days, hours, minutes, seconds % 60)
+    } else if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds % 60)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// The fields of `serverStatus` that are printed.
+fn summary(status: &mongodb::bson::Document) -> mongodb::bson::Document {
+    let subset = |key: &str, fields: &[&str]| {
+        let document = status.get_document(key).cloned().unwrap_or_default();
+        fields
+            .iter()
+            .filter_map(|field| Some((field.to_string(), document.get(field)?.clone())))
+            .collect::<mongodb::bson::Document>()
+    };
+    let mut summary = mongodb::bson::Document::new();
+    for key in ["host", "version", "process", "uptime"] {
+        if let Some(value) = status.get(key) {
+            summary.insert(key, value.clone());
+        }
+    }
+    summary.insert(
+        "connections",
+        subset("connections", &["current", "available", "totalCreated"]),
+    );
+    summary.insert(
+        "opcounters",
+        subset(
+            "opcounters",
+            &["insert", "query", "update", "delete", "getmore", "command"],
+        ),
+    );
+    summary.insert("mem", subset("mem", &["bits", "resident", "virtual"]));
+    summary
+}
+
+pub fn handler(
+    output_format: OutputFormat,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let status = client.database("admin").run_command(
+        mongodb::bson::doc! { "serverStatus": 1, "repl": 0, "metrics": 0, "locks": 0 },
+        None,
+    )?;
+    let summary = summary(&status);
+    if output_format != OutputFormat::Document {
+        writeln!(out, "{}", output_format.format_document(&summary))?;
+        return Ok(());
+    }
+
+    let number = |key: &str, field: &str| {
+        summary
+            .get_document(key)
+            .ok()
+            .and_then(|document| get_number(document, field))
+            .unwrap_or_default()
+    };
+    // The memory is reported in MiB.
+    let mebibytes = |field: &str| format_size(number("mem", field) * 1024.0 * 1024.0);
+    print_fields(
+        vec![
+            (
+                "Host",
+                summary.get_str("host").unwrap_or_default().to_string(),
+            ),
+            (
+                "Version",
+                summary.get_str("version").unwrap_or_default().to_string(),
+            ),
+            (
+                "Uptime",
+                format_uptime(get_number(&summary, "uptime").unwrap_or_default()),
+            ),
+            (
+                "Connections",
+                format!(
+                    "{} current, {} available, {} created",
+                    number("connections", "current"),
+                    number("connections", "available"),
+                    number("connections", "totalCreated")
+                ),
+            ),
+            ("Inserts", number("opcounters", "insert").to_string()),
+            ("Queries", number("opcounters", "query").to_string()),
+            ("Updates", number("opcounters", "update").to_string()),
+            ("Deletes", number("opcounters", "delete").to_string()),
+            ("getMores", number("opcounters", "getmore").to_string()),
+            ("Commands", number("opcounters", "command").to_string()),
+            ("Resident memory", mebibytes("resident")),
+            ("Virtual memory", mebibytes("virtual")),
+        ],
+        out,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_the_uptime() {
+        assert_eq!(format_uptime(12.0), "12s");
+        assert_eq!(format_uptime(3725.5), "1h 2m 5s");
+        assert_eq!(format_uptime(2.0 * 86400.0 + 60.0), "2d 0h 1m 0s");
+    }
+
+    #[test]
+    fn keeps_the_printed_fields() {
+        let status = mongodb::bson::doc! {
+            "host": "db:27017",
+            "version": "7.0.2",
+            "uptime": 3725.0,
+            "asserts": { "regular": 0 },
+            "connections": { "current": 3, "available": 100, "totalCreated": 8, "active": 1 },
+            "mem": { "bits": 64, "resident": 120, "virtual": 2500 },
+        };
+        assert_eq!(
+            summary(&status),
+            mongodb::bson::doc! {
+                "host": "db:27017",
+                "version": "7.0.2",
+                "uptime": 3725.0,
+                "connections": { "current": 3, "available": 100, "totalCreated": 8 },
+                "opcounters": {},
+                "mem": { "bits": 64, "resident": 120, "virtual": 2500 },
+            }
+        );
+    }
+}
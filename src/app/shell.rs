@@ -0,0 +1,279 @@
+use std::collections::BTreeSet;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use clap::{CommandFactory, Parser};
+
+use super::{to_handler, Command, Magg};
+use crate::history::record;
+use crate::shared::{connect, Config};
+
+const USE: &str = "use";
+const COLL: &str = "coll";
+const HELP: &str = "help";
+const EXIT: &str = "exit";
+const QUIT: &str = "quit";
+/// Number of documents sampled to complete field names.
+const FIELD_SAMPLE_SIZE: i64 = 20;
+/// Characters that separate the word being completed, so field names complete inside filters.
+const WORD_SEPARATORS: &[char] = &[' ', '\t', '{', '}', '[', ']', ',', ':', '"', '\''];
+
+/// Start an interactive shell that keeps the connection open and accepts the other
+/// subcommands as commands
+#[derive(clap::Args, Debug)]
+pub struct ShellArgs {
+    /// Where the command history is kept. Defaults to ~/.magg_history
+    #[arg(long)]
+    history_file: Option<String>,
+}
+
+/// Completes the commands, the database and collection names of the deployment and the
+/// field names of the current collection.
+#[derive(Default)]
+struct ShellHelper {
+    commands: Vec<String>,
+    database_names: Vec<String>,
+    collection_names: Vec<String>,
+    field_names: Vec<String>,
+}
+
+impl ShellHelper {
+    fn new() -> Self {
+        let mut commands = Magg::command()
+            .get_subcommands()
+            .filter(|command| !command.is_hide_set() && command.get_name() != "shell")
+            .map(|command| command.get_name().to_string())
+            .collect::<Vec<_>>();
+        commands.extend([USE, COLL, HELP, EXIT, QUIT].iter().map(|c| c.to_string()));
+        commands.sort();
+        ShellHelper {
+            commands,
+            ..Default::default()
+        }
+    }
+
+    /// Reload the names used for completion. Failures only leave the completions empty.
+    fn refresh(&mut self, client: &mongodb::sync::Client, config: &Config) {
+        self.database_names = client.list_database_names(None, None).unwrap_or_default();
+        let database = client.database(&config.database_name);
+        self.collection_names = database.list_collection_names(None).unwrap_or_default();
+        self.collection_names.sort();
+        self.field_names = sample_field_names(
+            &database.collection::<mongodb::bson::Document>(&config.collection_name),
+        );
+    }
+}
+
+/// The dotted paths of the fields found in a sample of the collection.
+fn sample_field_names(
+    collection: &mongodb::sync::Collection<mongodb::bson::Document>,
+) -> Vec<String> {
+    let mut field_names = BTreeSet::new();
+    if let Ok(cursor) = collection.aggregate(
+        vec![mongodb::bson::doc! { "$sample": { "size": FIELD_SAMPLE_SIZE } }],
+        None,
+    ) {
+        for document in cursor.flatten() {
+            collect_field_names("", &document, &mut field_names);
+        }
+    }
+    field_names.into_iter().collect()
+}
+
+fn collect_field_names(
+    prefix: &str,
+    document: &mongodb::bson::Document,
+    field_names: &mut BTreeSet<String>,
+) {
+    for (key, value) in document {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        if let mongodb::bson::Bson::Document(embedded) = value {
+            collect_field_names(&path, embedded, field_names);
+        }
+        field_names.insert(path);
+    }
+}
+
+fn candidates(names: &[String], prefix: &str) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| name.starts_with(prefix))
+        .cloned()
+        .collect()
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line
+            .rfind(WORD_SEPARATORS)
+            .map(|i| i + line[i..].chars().next().map_or(1, char::len_utf8))
+            .unwrap_or(0);
+        let word = &line[start..];
+        let previous = line[..start].split_whitespace().collect::<Vec<_>>();
+        let completions = match previous.as_slice() {
+            [] => candidates(&self.commands, word),
+            [USE] => candidates(&self.database_names, word),
+            [COLL] => candidates(&self.collection_names, word),
+            [.., "--database-name"] | [.., "--target-database-name"] => {
+                candidates(&self.database_names, word)
+            }
+            [.., "--collection-name"] | [.., "--target-collection-name"] => {
+                candidates(&self.collection_names, word)
+            }
+            // Field paths used as aggregation expressions, e.g. "$total".
+            _ if word.starts_with('$') => candidates(&self.field_names, &word[1..])
+                .into_iter()
+                .map(|field| format!("${}", field))
+                .collect(),
+            _ => candidates(&self.field_names, word),
+        };
+        Ok((start, completions))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+fn history_file(args: &ShellArgs) -> Option<std::path::PathBuf> {
+    match &args.history_file {
+        Some(path) => Some(path.into()),
+        None => {
+            std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".magg_history"))
+        }
+    }
+}
+
+/// Run one line of input. Returns false once the shell should exit.
+fn run_line(
+    line: &str,
+    client: &mongodb::sync::Client,
+    config: &mut Config,
+    helper: Option<&mut ShellHelper>,
+    out: &mut dyn std::io::Write,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let words = shell_words::split(line)?;
+    match words
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => {}
+        [EXIT] | [QUIT] => return Ok(false),
+        [USE, database_name] => {
+            config.database_name = database_name.to_string();
+            writeln!(out, "Switched to database {}", config.database_name)?;
+            if let Some(helper) = helper {
+                helper.refresh(client, config);
+            }
+        }
+        [COLL, collection_name] => {
+            config.collection_name = collection_name.to_string();
+            writeln!(out, "Switched to collection {}", config.collection_name)?;
+            if let Some(helper) = helper {
+                helper.refresh(client, config);
+            }
+        }
+        [USE, ..] => return Err("Usage: use <database>".into()),
+        [COLL, ..] => return Err("Usage: coll <collection>".into()),
+        _ => {
+            let magg = match Magg::try_parse_from(
+                std::iter::once(clap::crate_name!()).chain(words.iter().map(String::as_str)),
+            ) {
+                Ok(magg) => magg,
+                // Help and usage errors are printed instead of ending the shell.
+                Err(e) => {
+                    write!(out, "{}", e)?;
+                    return Ok(true);
+                }
+            };
+            if matches!(magg.command, Some(Command::Shell(_))) {
+                return Err("Already in the shell".into());
+            }
+            // Like outside of the shell, the history subcommand records what it re-runs itself.
+            let recorded = !matches!(magg.command, Some(Command::History(_)));
+            let result = config
+                .clone()
+                .with_overrides(&magg.global)
+                .and_then(|config| to_handler(magg, config, out));
+            // Recorded with the current namespace so it can be re-run outside of the shell.
+            let mut args = vec![
+                "--database-name".to_string(),
+                config.database_name.clone(),
+                "--collection-name".to_string(),
+                config.collection_name.clone(),
+            ];
+            args.extend(words);
+            if recorded {
+                let _ = record(&args, result.is_ok());
+            }
+            result?;
+        }
+    }
+    Ok(true)
+}
+
+pub fn handler(
+    args: &ShellArgs,
+    mut config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let mut helper = ShellHelper::new();
+    helper.refresh(&client, &config);
+    let mut editor = Editor::<ShellHelper>::new();
+    editor.set_helper(Some(helper));
+    let history_file = history_file(args);
+    if let Some(history_file) = &history_file {
+        // There is no history the first time the shell is started.
+        let _ = editor.load_history(history_file);
+    }
+
+    loop {
+        let prompt = format!("{}.{}> ", config.database_name, config.collection_name);
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str());
+        match run_line(&line, &client, &mut config, editor.helper_mut(), out) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    if let Some(history_file) = &history_file {
+        editor.save_history(history_file)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use crate::shared::{connect, format_size, get_number, print_fields, Config, OutputFormat};
+
+/// The storage statistics of the collection, added up over the shards.
+fn summary(namespace: &str, storage_stats: &[mongodb::bson::Document]) -> mongodb::bson::Document {
+    let total = |field: &str| {
+        storage_stats
+            .iter()
+            .filter_map(|stats| get_number(stats, field))
+            .sum::<f64>() as i64
+    };
+    let mut index_sizes = mongodb::bson::Document::new();
+    for stats in storage_stats {
+        for (name, size) in stats.get_document("indexSizes").into_iter().flatten() {
+            let size = crate::validation::as_number(size).unwrap_or_default() as i64;
+            let previous = index_sizes.get_i64(name).unwrap_or_default();
+            index_sizes.insert(name, previous + size);
+        }
+    }
+    let count = total("count");
+    let size = total("size");
+    mongodb::bson::doc! {
+        "ns": namespace,
+        "count": count,
+        "size": size,
+        "avgObjSize": if count == 0 { 0 } else { size / count },
+        "storageSize": total("storageSize"),
+        "nindexes": index_sizes.len() as i64,
+        "totalIndexSize": total("totalIndexSize"),
+        "indexSizes": index_sizes,
+    }
+}
+
+pub fn handler(
+    output_format: OutputFormat,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let collection = client
+        .database(&config.database_name)
+        .collection::<mongodb::bson::Document>(&config.collection_name);
+    // One document per shard.
+    let storage_stats = collection
+        .aggregate(
+            vec![mongodb::bson::doc! { "$collStats": { "storageStats": {} } }],
+            None,
+        )?
+        .map(|result| Ok(result?.get_document("storageStats")?.clone()))
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+    let summary = summary(
+        &format!("{}.{}", config.database_name, config.collection_name),
+        &storage_stats,
+    );
+    if output_format != OutputFormat::Document {
+        writeln!(out, "{}", output_format.format_document(&summary))?;
+        return Ok(());
+    }
+
+    let size = |field: &str| format_size(get_number(&summary, field).unwrap_or_default());
+    let mut fields = vec![
+        ("Namespace", summary.get_str("ns")?.to_string()),
+        ("Documents", summary.get_i64("count")?.to_string()),
+        ("Average document size", size("avgObjSize")),
+        ("Data size", size("size")),
+        ("Storage size", size("storageSize")),
+        ("Indexes", summary.get_i64("nindexes")?.to_string()),
+        ("Total index size", size("totalIndexSize")),
+    ];
+    let index_sizes = summary.get_document("indexSizes")?;
+    let index_names = index_sizes
+        .keys()
+        .map(|name| format!("Index {}", name))
+        .collect::<Vec<_>>();
+    for (name, label) in index_sizes.keys().zip(index_names.iter()) {
+        fields.push((
+            label,
+            format_size(get_number(index_sizes, name).unwrap_or_default()),
+        ));
+    }
+    print_fields(fields, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn adds_up_the_shards() {
+        let summary = summary(
+            "test.items",
+            &[
+                doc! {
+                    "count": 2, "size": 100, "avgObjSize": 50, "storageSize": 4096,
+                    "totalIndexSize": 8192, "indexSizes": { "_id_": 4096, "name_1": 4096 },
+                },
+                doc! {
+                    "count": 2_i64, "size": 60.0, "avgObjSize": 30, "storageSize": 4096,
+                    "totalIndexSize": 4096, "indexSizes": { "_id_": 4096 },
+                },
+            ],
+        );
+        assert_eq!(
+            summary,
+            doc! {
+                "ns": "test.items",
+                "count": 4_i64,
+                "size": 160_i64,
+                "avgObjSize": 40_i64,
+                "storageSize": 8192_i64,
+                "nindexes": 2_i64,
+                "totalIndexSize": 12288_i64,
+                "indexSizes": { "_id_": 8192_i64, "name_1": 4096_i64 },
+            }
+        );
+    }
+}
@@ -0,0 +1,386 @@
+use serde::Deserialize;
+
+use crate::shared::{
+    connect, json_to_bson_document, parse_read_concern, parse_write_concern, read_json_lines,
+    stringify_bson, Config, InputType,
+};
+
+/// Execute a list of operations across collections in a single transaction
+#[derive(clap::Args, Debug)]
+pub struct TransactionArgs {
+    /// Get the operations directly as an argument. Expects JSON lines with one operation per
+    /// line, e.g. {"collection": "users", "delete": {"filter": {...}}}
+    #[arg(long)]
+    input_operations: Option<String>,
+    /// Get the operations from a file. Expects JSON lines
+    #[arg(long)]
+    input_file: Option<String>,
+    /// The read concern of the transaction, e.g. 'snapshot' or 'majority'
+    #[arg(long)]
+    read_concern: Option<String>,
+    /// The write concern of the transaction, e.g. 'majority' or a number of nodes
+    #[arg(long)]
+    write_concern: Option<String>,
+    /// How many times to retry the transaction on transient errors
+    #[arg(long, default_value_t = 3)]
+    max_retries: usize,
+}
+
+/// A single line of the operations file.
+/// The collection defaults to the configured one when it is not given.
+#[derive(Deserialize, Debug)]
+struct TransactionOperation {
+    collection: Option<String>,
+    #[serde(flatten)]
+    action: TransactionAction,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+enum TransactionAction {
+    /// Either a single document or an array of documents.
+    Create(serde_json::Value),
+    Update {
+        filter: serde_json::Value,
+        update: serde_json::Value,
+        #[serde(default)]
+        many: bool,
+        #[serde(default)]
+        upsert: bool,
+    },
+    Delete {
+        filter: serde_json::Value,
+        #[serde(default)]
+        many: bool,
+    },
+}
+
+enum Statement {
+    Create(Vec<mongodb::bson::Document>),
+    Update {
+        filter: mongodb::bson::Document,
+        update: mongodb::options::UpdateModifications,
+        many: bool,
+        upsert: bool,
+    },
+    Delete {
+        filter: mongodb::bson::Document,
+        many: bool,
+    },
+}
+
+fn to_statement(action: TransactionAction) -> Result<Statement, Box<dyn std::error::Error>> {
+    let statement = match action {
+        TransactionAction::Create(serde_json::Value::Array(arr)) => Statement::Create(
+            arr.iter()
+                .map(|v| json_to_bson_document(v, "create"))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        TransactionAction::Create(value) => {
+            Statement::Create(vec![json_to_bson_document(&value, "create")?])
+        }
+        TransactionAction::Update {
+            filter,
+            update,
+            many,
+            upsert,
+        } => Statement::Update {
+            filter: json_to_bson_document(&filter, "filter")?,
+            update: match update {
+                serde_json::Value::Array(stages) => {
+                    mongodb::options::UpdateModifications::Pipeline(
+                        stages
+                            .iter()
+                            .map(|v| json_to_bson_document(v, "update"))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )
+                }
+                o => mongodb::options::UpdateModifications::Document(json_to_bson_document(
+                    &o, "update",
+                )?),
+            },
+            many,
+            upsert,
+        },
+        TransactionAction::Delete { filter, many } => Statement::Delete {
+            filter: json_to_bson_document(&filter, "filter")?,
+            many,
+        },
+    };
+    Ok(statement)
+}
+
+/// Run every statement inside the session's current transaction.
+/// On failure, returns the line number of the statement that failed alongside the error.
+fn execute_statements(
+    database: &mongodb::sync::Database,
+    statements: &[(usize, String, Statement)],
+    session: &mut mongodb::sync::ClientSession,
+) -> Result<Vec<String>, (usize, mongodb::error::Error)> {
+    let mut report = Vec::with_capacity(statements.len());
+    for (line, collection_name, statement) in statements {
+        let collection = database.collection::<mongodb::bson::Document>(collection_name);
+        let line = *line;
+        let message = match statement {
+            Statement::Create(documents) => {
+                let result = collection
+                    .insert_many_with_session(documents.iter(), None, session)
+                    .map_err(|e| (line, e))?;
+                format!(
+                    "inserted {} document{} into '{}'",
+                    result.inserted_ids.len(),
+                    if result.inserted_ids.len() == 1 {
+                        ""
+                    } else {
+                        "s"
+                    },
+                    collection_name
+                )
+            }
+            Statement::Update {
+                filter,
+                update,
+                many,
+                upsert,
+            } => {
+                let options = mongodb::options::UpdateOptions::builder()
+                    .upsert(*upsert)
+                    .build();
+                let result = if *many {
+                    collection.update_many_with_session(
+                        filter.clone(),
+                        update.clone(),
+                        options,
+                        session,
+                    )
+                } else {
+                    collection.update_one_with_session(
+                        filter.clone(),
+                        update.clone(),
+                        options,
+                        session,
+                    )
+                }
+                .map_err(|e| (line, e))?;
+                format!(
+                    "matched {} and modified {} document{}{} in '{}'",
+                    result.matched_count,
+                    result.modified_count,
+                    if result.modified_count == 1 { "" } else { "s" },
+                    result
+                        .upserted_id
+                        .map(|id| format!(", upserted _id {}", stringify_bson(&id)))
+                        .unwrap_or_default(),
+                    collection_name
+                )
+            }
+            Statement::Delete { filter, many } => {
+                let result = if *many {
+                    collection.delete_many_with_session(filter.clone(), None, session)
+                } else {
+                    collection.delete_one_with_session(filter.clone(), None, session)
+                }
+                .map_err(|e| (line, e))?;
+                format!(
+                    "deleted {} document{} from '{}'",
+                    result.deleted_count,
+                    if result.deleted_count == 1 { "" } else { "s" },
+                    collection_name
+                )
+            }
+        };
+        report.push(format!("Line {}: {}", line, message));
+    }
+    Ok(report)
+}
+
+/// What follows a failed statement or commit, once the commit itself is no longer retried.
+#[derive(Debug, PartialEq)]
+enum Recovery {
+    /// Run the whole transaction again.
+    Retry,
+    /// The transaction was aborted, nothing was applied.
+    RolledBack,
+    /// The commit may or may not have been applied.
+    Unknown,
+}
+
+/// Whether a commit that failed with these labels is sent again. The server deduplicates the
+/// commit, so this is safe while its result is unknown.
+fn retries_commit(
+    has_label: impl Fn(&str) -> bool,
+    commit_attempt: usize,
+    max_retries: usize,
+) -> bool {
+    has_label(mongodb::error::UNKNOWN_TRANSACTION_COMMIT_RESULT) && commit_attempt < max_retries
+}
+
+/// A transient error retries the transaction from the start. Otherwise a failed statement
+/// aborts the transaction, while a commit whose result stayed unknown may have been applied.
+fn recovery(
+    has_label: impl Fn(&str) -> bool,
+    committing: bool,
+    attempt: usize,
+    max_retries: usize,
+) -> Recovery {
+    if has_label(mongodb::error::TRANSIENT_TRANSACTION_ERROR) && attempt < max_retries {
+        Recovery::Retry
+    } else if committing && has_label(mongodb::error::UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+        Recovery::Unknown
+    } else {
+        Recovery::RolledBack
+    }
+}
+
+/// The server may have applied a commit whose result is unknown, so only a commit that failed
+/// for another reason is known to be rolled back.
+fn commit_failure_outcome(unknown_result: bool) -> &'static str {
+    if unknown_result {
+        "The outcome of the transaction is unknown. \
+        Please verify whether the changes were applied"
+    } else {
+        "The transaction was rolled back. No changes were applied"
+    }
+}
+
+pub fn handler(
+    args: &TransactionArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let database = client.database(&config.database_name);
+    let max_retries = args.max_retries;
+    let handle = InputType::from_args(
+        args.input_file.as_deref(),
+        args.input_operations.as_deref(),
+        "input-operations",
+    )?;
+    let statements = read_json_lines::<TransactionOperation, _>(handle.into_reader())?
+        .into_iter()
+        .map(|(line, operation)| {
+            let collection_name = operation
+                .collection
+                .unwrap_or_else(|| config.collection_name.clone());
+            to_statement(operation.action)
+                .map(|statement| (line, collection_name, statement))
+                .map_err(|e| format!("Line {}: {}", line, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let transaction_options = mongodb::options::TransactionOptions::builder()
+        .read_concern(args.read_concern.as_deref().map(parse_read_concern))
+        .write_concern(args.write_concern.as_deref().map(parse_write_concern))
+        .build();
+
+    let mut session = client.start_session(None)?;
+    let mut attempt = 0;
+    loop {
+        session.start_transaction(transaction_options.clone())?;
+        let (line, e) = match execute_statements(&database, &statements, &mut session) {
+            Ok(report) => {
+                let mut commit_attempt = 0;
+                let commit_result = loop {
+                    match session.commit_transaction() {
+                        Err(e)
+                            if retries_commit(
+                                |label| e.contains_label(label),
+                                commit_attempt,
+                                max_retries,
+                            ) =>
+                        {
+                            commit_attempt += 1;
+                        }
+                        o => break o,
+                    }
+                };
+                match commit_result {
+                    Ok(()) => {
+                        for line in report.iter() {
+                            writeln!(out, "{}", line)?;
+                        }
+                        writeln!(
+                            out,
+                            "Committed {} operation{}",
+                            statements.len(),
+                            if statements.len() == 1 { "" } else { "s" }
+                        )?;
+                        return Ok(());
+                    }
+                    Err(e) => (None, e),
+                }
+            }
+            Err((line, e)) => {
+                // The server may have already aborted the transaction on its end.
+                let _ = session.abort_transaction();
+                (Some(line), e)
+            }
+        };
+        match recovery(
+            |label| e.contains_label(label),
+            line.is_none(),
+            attempt,
+            max_retries,
+        ) {
+            Recovery::Retry => {
+                attempt += 1;
+                eprintln!(
+                    "Transient transaction error, retrying ({}/{}): {}",
+                    attempt, max_retries, e
+                );
+            }
+            recovery => {
+                match line {
+                    Some(line) => writeln!(out, "Transaction failed at line {}: {}", line, e)?,
+                    None => writeln!(out, "Failed to commit the transaction: {}", e)?,
+                }
+                let unknown_result = recovery == Recovery::Unknown;
+                writeln!(out, "{}", commit_failure_outcome(unknown_result))?;
+                return Err(if unknown_result {
+                    "Transaction outcome unknown".into()
+                } else {
+                    "Transaction aborted".into()
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(labels: &'static [&'static str]) -> impl Fn(&str) -> bool {
+        move |label| labels.contains(&label)
+    }
+
+    const UNKNOWN: &[&str] = &[mongodb::error::UNKNOWN_TRANSACTION_COMMIT_RESULT];
+    const TRANSIENT: &[&str] = &[mongodb::error::TRANSIENT_TRANSACTION_ERROR];
+
+    #[test]
+    fn retries_the_commit_while_its_result_is_unknown() {
+        assert!(retries_commit(labels(UNKNOWN), 0, 2));
+        assert!(retries_commit(labels(UNKNOWN), 1, 2));
+        assert!(!retries_commit(labels(UNKNOWN), 2, 2));
+        assert!(!retries_commit(labels(TRANSIENT), 0, 2));
+        assert!(!retries_commit(labels(&[]), 0, 2));
+    }
+
+    #[test]
+    fn retries_the_transaction_after_transient_errors() {
+        assert_eq!(recovery(labels(TRANSIENT), false, 0, 1), Recovery::Retry);
+        assert_eq!(recovery(labels(TRANSIENT), true, 0, 1), Recovery::Retry);
+        assert_eq!(
+            recovery(labels(TRANSIENT), false, 1, 1),
+            Recovery::RolledBack
+        );
+        assert_eq!(recovery(labels(&[]), false, 0, 1), Recovery::RolledBack);
+    }
+
+    #[test]
+    fn only_reports_a_rollback_when_the_commit_result_is_known() {
+        assert_eq!(recovery(labels(UNKNOWN), true, 0, 1), Recovery::Unknown);
+        assert_eq!(recovery(labels(&[]), true, 0, 1), Recovery::RolledBack);
+        // A failed statement aborts the transaction before the commit.
+        assert_eq!(recovery(labels(UNKNOWN), false, 0, 1), Recovery::RolledBack);
+    }
+}
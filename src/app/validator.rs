@@ -0,0 +1,199 @@
+use std::convert::TryFrom;
+
+use crate::shared::{connect, first_batch, parse_document, stringify_bson, Config};
+use crate::validation::validate_document;
+
+/// Manage the validator of the collection
+#[derive(clap::Args, Debug)]
+#[command(arg_required_else_help = true)]
+pub struct ValidatorArgs {
+    #[command(subcommand)]
+    command: ValidatorCommand,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ValidatorCommand {
+    /// Print the validator, validation level and validation action
+    Show,
+    Set(SetArgs),
+    Check(CheckArgs),
+}
+
+/// Replace the validator of the collection
+#[derive(clap::Args, Debug)]
+struct SetArgs {
+    /// A file containing the validator, e.g. {"$jsonSchema": {...}} or a query
+    #[arg(long, required_unless_present_any = ["validation_level", "validation_action"])]
+    input_file: Option<String>,
+    /// Which documents are validated
+    #[arg(long, value_parser = ["off", "strict", "moderate"])]
+    validation_level: Option<String>,
+    /// Whether invalid documents are rejected or only logged
+    #[arg(long, value_parser = ["error", "warn"])]
+    validation_action: Option<String>,
+}
+
+/// Report the existing documents that would fail the validator and why
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    /// Only check the documents that match this filter
+    #[arg(long, value_parser = parse_document)]
+    input_filter: Option<mongodb::bson::Document>,
+    /// Check against the validator in this file instead of the current one
+    #[arg(long)]
+    input_file: Option<String>,
+}
+
+/// The `options` of the collection as returned by `listCollections`.
+fn collection_options(
+    database: &mongodb::sync::Database,
+    collection_name: &str,
+) -> Result<mongodb::bson::Document, Box<dyn std::error::Error>> {
+    let reply = database.run_command(
+        mongodb::bson::doc! {
+            "listCollections": 1,
+            "filter": { "name": collection_name },
+        },
+        None,
+    )?;
+    first_batch(&reply)?
+        .into_iter()
+        .next()
+        .map(|c| c.get_document("options").cloned().unwrap_or_default())
+        .ok_or_else(|| format!("There are no collection named '{}'", collection_name).into())
+}
+
+fn read_validator(path: &str) -> Result<mongodb::bson::Document, Box<dyn std::error::Error>> {
+    match serde_json::from_reader::<_, serde_json::Value>(std::fs::File::open(path)?)? {
+        serde_json::Value::Object(o) => Ok(mongodb::bson::Document::try_from(o)?),
+        _ => Err("The validator must be an object".into()),
+    }
+}
+
+fn show(
+    database: &mongodb::sync::Database,
+    config: &Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let options = collection_options(database, &config.collection_name)?;
+    match options.get_document("validator") {
+        Ok(validator) => writeln!(
+            out,
+            "{}",
+            serde_json::to_string_pretty(
+                &mongodb::bson::Bson::Document(validator.clone()).into_relaxed_extjson()
+            )?
+        )?,
+        Err(_) => writeln!(out, "No validator")?,
+    }
+    writeln!(
+        out,
+        "Validation level: {}",
+        options.get_str("validationLevel").unwrap_or("strict")
+    )?;
+    writeln!(
+        out,
+        "Validation action: {}",
+        options.get_str("validationAction").unwrap_or("error")
+    )?;
+    Ok(())
+}
+
+fn set(
+    args: &SetArgs,
+    database: &mongodb::sync::Database,
+    config: &Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = mongodb::bson::doc! { "collMod": &config.collection_name };
+    if let Some(path) = &args.input_file {
+        command.insert("validator", read_validator(path)?);
+    }
+    if let Some(level) = &args.validation_level {
+        command.insert("validationLevel", level);
+    }
+    if let Some(action) = &args.validation_action {
+        command.insert("validationAction", action);
+    }
+    database.run_command(command, None)?;
+    writeln!(
+        out,
+        "Updated the validator of {}.{}",
+        config.database_name, config.collection_name
+    )?;
+    Ok(())
+}
+
+fn check(
+    args: &CheckArgs,
+    database: &mongodb::sync::Database,
+    config: &Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let collection =
+        database.collection::<mongodb::bson::document::Document>(&config.collection_name);
+    let validator = match &args.input_file {
+        Some(path) => read_validator(path)?,
+        None => collection_options(database, &config.collection_name)?
+            .get_document("validator")
+            .cloned()
+            .map_err(|_| "The collection has no validator")?,
+    };
+    let check_filter = args.input_filter.clone().unwrap_or_default();
+    let checked = collection.count_documents(check_filter.clone(), None)?;
+
+    // The server decides which documents fail, the reasons are worked out locally from the
+    // `$jsonSchema` when there is one.
+    let json_schema = validator.get_document("$jsonSchema").ok().cloned();
+    let failing = collection.find(
+        mongodb::bson::doc! {
+            "$and": [check_filter, { "$nor": [validator.clone()] }],
+        },
+        None,
+    )?;
+    let mut failed = 0;
+    for result in failing {
+        let document = result?;
+        failed += 1;
+        writeln!(
+            out,
+            "_id: {}",
+            document
+                .get("_id")
+                .map(|id| stringify_bson(id).to_string())
+                .unwrap_or_default()
+        )?;
+        let violations = json_schema
+            .as_ref()
+            .map(|schema| validate_document(schema, &document))
+            .unwrap_or_default();
+        if violations.is_empty() {
+            writeln!(out, "  does not match the query validator")?;
+        }
+        for violation in violations {
+            writeln!(out, "  {}", violation)?;
+        }
+    }
+    writeln!(
+        out,
+        "{} of {} document{} would fail validation",
+        failed,
+        checked,
+        if checked == 1 { "" } else { "s" }
+    )?;
+    Ok(())
+}
+
+pub fn handler(
+    args: &ValidatorArgs,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let database = client.database(&config.database_name);
+    match &args.command {
+        ValidatorCommand::Show => show(&database, &config, out),
+        ValidatorCommand::Set(args) => set(args, &database, &config, out),
+        ValidatorCommand::Check(args) => check(args, &database, &config, out),
+    }
+}
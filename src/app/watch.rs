@@ -0,0 +1,121 @@
+use std::convert::TryFrom;
+
+use crate::shared::{connect, parse_pipeline_arg, Config, OutputFormat, Stages};
+
+/// Print the changes happening to a collection, database or the whole deployment
+#[derive(clap::Args, Debug)]
+pub struct WatchArgs {
+    /// What to watch for changes
+    #[arg(long, value_enum, default_value_t = Scope::Collection)]
+    scope: Scope,
+    /// Additional stages applied to the change events, e.g.
+    /// [{"$match": {"operationType": "insert"}}]
+    #[arg(long, value_parser = parse_pipeline_arg)]
+    pipeline: Option<Stages>,
+    /// Pass 'updateLookup' to include the current version of the document in update events
+    #[arg(long, value_parser = ["default", "updateLookup"])]
+    full_document: Option<String>,
+    /// Resume after the event with the given resume token
+    #[arg(long, value_parser = parse_resume_token)]
+    resume_after: Option<mongodb::bson::Document>,
+    /// Only return changes that happened at or after the given timestamp. Expects '<seconds>'
+    /// or '<seconds>:<increment>'
+    #[arg(long, value_parser = parse_timestamp, conflicts_with = "resume_after")]
+    start_at_operation_time: Option<mongodb::bson::Timestamp>,
+    /// Save the resume token of the last printed event to this file. If the file exists, the
+    /// watch resumes from the token it contains
+    #[arg(long)]
+    resume_token_file: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+enum Scope {
+    Collection,
+    Database,
+    Deployment,
+}
+
+fn parse_resume_token(s: &str) -> Result<mongodb::bson::Document, String> {
+    match serde_json::from_str::<serde_json::Value>(s).map_err(|e| e.to_string())? {
+        serde_json::Value::Object(o) => {
+            mongodb::bson::Document::try_from(o).map_err(|e| e.to_string())
+        }
+        _ => Err("Resume token must be an object".to_string()),
+    }
+}
+
+fn parse_timestamp(s: &str) -> Result<mongodb::bson::Timestamp, String> {
+    let mut parts = s.splitn(2, ':');
+    let time = parts
+        .next()
+        .unwrap_or_default()
+        .parse::<u32>()
+        .map_err(|e| e.to_string())?;
+    let increment = parts
+        .next()
+        .map(|s| s.parse::<u32>())
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    Ok(mongodb::bson::Timestamp {
+        time,
+        increment: increment.unwrap_or(0),
+    })
+}
+
+pub fn handler(
+    args: &WatchArgs,
+    output_format: OutputFormat,
+    config: Config,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(&config.connection_uri)?;
+    let resume_token_file = args.resume_token_file.as_deref();
+
+    let mut change_stream = mongodb::bson::Document::new();
+    if let Some(full_document) = &args.full_document {
+        change_stream.insert("fullDocument", full_document);
+    }
+    if let Some(token) = &args.resume_after {
+        change_stream.insert("resumeAfter", token.clone());
+    } else if let Some(timestamp) = args.start_at_operation_time {
+        change_stream.insert("startAtOperationTime", timestamp);
+    } else if let Some(file) = resume_token_file.filter(|f| std::path::Path::new(f).exists()) {
+        change_stream.insert(
+            "resumeAfter",
+            parse_resume_token(&std::fs::read_to_string(file)?)?,
+        );
+    }
+
+    if args.scope == Scope::Deployment {
+        change_stream.insert("allChangesForCluster", true);
+    }
+    let mut pipeline = vec![mongodb::bson::doc! { "$changeStream": change_stream }];
+    if let Some(stages) = &args.pipeline {
+        pipeline.extend(stages.iter().cloned());
+    }
+
+    let cursor = match args.scope {
+        // Deployment-wide change streams must be opened against the admin database.
+        Scope::Deployment => client.database("admin").aggregate(pipeline, None)?,
+        Scope::Database => client
+            .database(&config.database_name)
+            .aggregate(pipeline, None)?,
+        Scope::Collection => client
+            .database(&config.database_name)
+            .collection::<mongodb::bson::Document>(&config.collection_name)
+            .aggregate(pipeline, None)?,
+    };
+    for result in cursor {
+        let event = result?;
+        writeln!(out, "{}", output_format.format_document(&event))?;
+        if let (Some(file), Ok(token)) = (resume_token_file, event.get_document("_id")) {
+            std::fs::write(
+                file,
+                mongodb::bson::Bson::Document(token.clone())
+                    .into_relaxed_extjson()
+                    .to_string(),
+            )?;
+        }
+    }
+    Ok(())
+}
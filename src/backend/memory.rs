@@ -0,0 +1,811 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use super::{Backend, Documents};
+use crate::shared::get_path;
+use crate::validation::{as_number, bson_equal, is_number};
+
+type Collections = BTreeMap<String, Vec<mongodb::bson::Document>>;
+
+/// Keeps the collections in memory. Filters support the comparison, logical, `$exists`,
+/// `$regex` and `$size` operators, and pipelines the `$documents`, `$match`, `$project`,
+/// `$sort`, `$skip`, `$limit`, `$count` and `$out` stages. Anything else is an error.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    databases: RefCell<BTreeMap<String, Collections>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        MemoryBackend::default()
+    }
+
+    /// The documents of a collection, in insertion order.
+    pub fn documents(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+    ) -> Vec<mongodb::bson::Document> {
+        self.databases
+            .borrow()
+            .get(database_name)
+            .and_then(|collections| collections.get(collection_name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn matching(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<&mongodb::bson::Document>,
+    ) -> Result<Vec<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+        let mut matching = vec![];
+        for document in self.documents(database_name, collection_name) {
+            if filter.map_or(Ok(true), |filter| matches_filter(&document, filter))? {
+                matching.push(document);
+            }
+        }
+        Ok(matching)
+    }
+}
+
+fn into_documents(documents: Vec<mongodb::bson::Document>) -> Documents {
+    Box::new(documents.into_iter().map(Ok))
+}
+
+fn unsupported(what: &str) -> Box<dyn std::error::Error> {
+    format!("The in-memory backend does not support {}", what).into()
+}
+
+/// The values at a dotted path. Arrays along the way are searched element by element and an
+/// array at the end of the path contributes both itself and its elements, like the server does.
+fn values_at(value: &mongodb::bson::Bson, path: &[&str], values: &mut Vec<mongodb::bson::Bson>) {
+    match (value, path) {
+        (mongodb::bson::Bson::Array(array), []) => {
+            values.push(value.clone());
+            values.extend(array.iter().cloned());
+        }
+        (value, []) => values.push(value.clone()),
+        (mongodb::bson::Bson::Document(document), [key, rest @ ..]) => {
+            if let Some(value) = document.get(*key) {
+                values_at(value, rest, values);
+            }
+        }
+        (mongodb::bson::Bson::Array(array), path) => {
+            for element in array {
+                if let mongodb::bson::Bson::Document(_) = element {
+                    values_at(element, path, values);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The position of the type in the server's sort order.
+fn type_rank(value: Option<&mongodb::bson::Bson>) -> u8 {
+    match value {
+        Some(mongodb::bson::Bson::MinKey) => 0,
+        None | Some(mongodb::bson::Bson::Null) | Some(mongodb::bson::Bson::Undefined) => 1,
+        Some(value) if is_number(value) => 2,
+        Some(mongodb::bson::Bson::String(_)) | Some(mongodb::bson::Bson::Symbol(_)) => 3,
+        Some(mongodb::bson::Bson::Document(_)) => 4,
+        Some(mongodb::bson::Bson::Array(_)) => 5,
+        Some(mongodb::bson::Bson::Binary(_)) => 6,
+        Some(mongodb::bson::Bson::ObjectId(_)) => 7,
+        Some(mongodb::bson::Bson::Boolean(_)) => 8,
+        Some(mongodb::bson::Bson::DateTime(_)) => 9,
+        Some(mongodb::bson::Bson::Timestamp(_)) => 10,
+        Some(mongodb::bson::Bson::RegularExpression(_)) => 11,
+        Some(mongodb::bson::Bson::MaxKey) => 13,
+        Some(_) => 12,
+    }
+}
+
+/// Compares values of the same kind. Numbers compare by value regardless of their BSON type.
+fn compare(a: &mongodb::bson::Bson, b: &mongodb::bson::Bson) -> Option<Ordering> {
+    match (a, b) {
+        (a, b) if is_number(a) && is_number(b) => as_number(a)?.partial_cmp(&as_number(b)?),
+        (mongodb::bson::Bson::String(a), mongodb::bson::Bson::String(b)) => Some(a.cmp(b)),
+        (mongodb::bson::Bson::Boolean(a), mongodb::bson::Bson::Boolean(b)) => Some(a.cmp(b)),
+        (mongodb::bson::Bson::DateTime(a), mongodb::bson::Bson::DateTime(b)) => Some(a.cmp(b)),
+        (mongodb::bson::Bson::ObjectId(a), mongodb::bson::Bson::ObjectId(b)) => {
+            Some(a.bytes().cmp(&b.bytes()))
+        }
+        (mongodb::bson::Bson::Timestamp(a), mongodb::bson::Bson::Timestamp(b)) => {
+            Some((a.time, a.increment).cmp(&(b.time, b.increment)))
+        }
+        (a, b) if bson_equal(a, b) => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+fn sort_order(
+    a: &mongodb::bson::Document,
+    b: &mongodb::bson::Document,
+    sort: &mongodb::bson::Document,
+) -> Ordering {
+    for (path, direction) in sort {
+        let (a, b) = (get_path(a, path), get_path(b, path));
+        let order = type_rank(a).cmp(&type_rank(b)).then_with(|| match (a, b) {
+            (Some(a), Some(b)) => compare(a, b).unwrap_or(Ordering::Equal),
+            _ => Ordering::Equal,
+        });
+        let order = if as_number(direction).is_some_and(|d| d < 0.0) {
+            order.reverse()
+        } else {
+            order
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    Ordering::Equal
+}
+
+fn is_operator_document(value: &mongodb::bson::Bson) -> bool {
+    match value {
+        mongodb::bson::Bson::Document(document) => document
+            .keys()
+            .next()
+            .is_some_and(|key| key.starts_with('$')),
+        _ => false,
+    }
+}
+
+fn equals_any(values: &[mongodb::bson::Bson], expected: &mongodb::bson::Bson) -> bool {
+    match expected {
+        // A null matches missing fields too.
+        mongodb::bson::Bson::Null => {
+            values.is_empty() || values.contains(&mongodb::bson::Bson::Null)
+        }
+        mongodb::bson::Bson::RegularExpression(regex) => values
+            .iter()
+            .any(|v| *v == *expected || matches_regex(v, &regex.pattern, &regex.options)),
+        expected => values.iter().any(|v| bson_equal(v, expected)),
+    }
+}
+
+fn matches_regex(value: &mongodb::bson::Bson, pattern: &str, options: &str) -> bool {
+    let flags = options
+        .chars()
+        .filter(|c| matches!(c, 'i' | 'm' | 's' | 'x'))
+        .collect::<String>();
+    let pattern = if flags.is_empty() {
+        pattern.to_string()
+    } else {
+        format!("(?{}){}", flags, pattern)
+    };
+    match (value, regex::Regex::new(&pattern)) {
+        (mongodb::bson::Bson::String(s), Ok(regex)) => regex.is_match(s),
+        _ => false,
+    }
+}
+
+fn array_of<'a>(
+    operator: &str,
+    value: &'a mongodb::bson::Bson,
+) -> Result<&'a Vec<mongodb::bson::Bson>, Box<dyn std::error::Error>> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("{} needs an array", operator).into())
+}
+
+fn matches_operators(
+    values: &[mongodb::bson::Bson],
+    operators: &mongodb::bson::Document,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    for (operator, argument) in operators {
+        let comparison = |accept: fn(Ordering) -> bool| {
+            values
+                .iter()
+                .any(|v| compare(v, argument).is_some_and(accept))
+        };
+        let matched = match operator.as_str() {
+            "$eq" => equals_any(values, argument),
+            "$ne" => !equals_any(values, argument),
+            "$gt" => comparison(|o| o == Ordering::Greater),
+            "$gte" => comparison(|o| o != Ordering::Less),
+            "$lt" => comparison(|o| o == Ordering::Less),
+            "$lte" => comparison(|o| o != Ordering::Greater),
+            "$in" => array_of(operator, argument)?
+                .iter()
+                .any(|expected| equals_any(values, expected)),
+            "$nin" => !array_of(operator, argument)?
+                .iter()
+                .any(|expected| equals_any(values, expected)),
+            "$exists" => {
+                let exists = match argument {
+                    mongodb::bson::Bson::Boolean(b) => *b,
+                    o => as_number(o).is_some_and(|n| n != 0.0),
+                };
+                values.is_empty() != exists
+            }
+            "$not" => match argument {
+                mongodb::bson::Bson::Document(operators) => !matches_operators(values, operators)?,
+                mongodb::bson::Bson::RegularExpression(_) => !equals_any(values, argument),
+                _ => return Err("$not needs an operator document or a regex".into()),
+            },
+            "$regex" => {
+                let pattern = match argument {
+                    mongodb::bson::Bson::String(pattern) => pattern.clone(),
+                    mongodb::bson::Bson::RegularExpression(regex) => regex.pattern.clone(),
+                    _ => return Err("$regex needs a string".into()),
+                };
+                let options = operators.get_str("$options").unwrap_or_default();
+                values.iter().any(|v| matches_regex(v, &pattern, options))
+            }
+            "$options" => true,
+            "$size" => values.iter().any(|v| match v {
+                mongodb::bson::Bson::Array(array) => {
+                    as_number(argument) == Some(array.len() as f64)
+                }
+                _ => false,
+            }),
+            o => return Err(unsupported(&format!("the {} operator", o))),
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether the document matches a query filter.
+pub fn matches_filter(
+    document: &mongodb::bson::Document,
+    filter: &mongodb::bson::Document,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    for (key, condition) in filter {
+        let matched = match key.as_str() {
+            "$and" | "$or" | "$nor" => {
+                let mut results = vec![];
+                for clause in array_of(key, condition)? {
+                    let clause = clause
+                        .as_document()
+                        .ok_or_else(|| format!("{} needs an array of documents", key))?;
+                    results.push(matches_filter(document, clause)?);
+                }
+                match key.as_str() {
+                    "$and" => results.iter().all(|r| *r),
+                    "$or" => results.iter().any(|r| *r),
+                    _ => !results.iter().any(|r| *r),
+                }
+            }
+            o if o.starts_with('$') => return Err(unsupported(&format!("the {} operator", o))),
+            path => {
+                let mut values = vec![];
+                let keys = path.split('.').collect::<Vec<_>>();
+                values_at(
+                    &mongodb::bson::Bson::Document(document.clone()),
+                    &keys,
+                    &mut values,
+                );
+                match condition {
+                    mongodb::bson::Bson::Document(operators) if is_operator_document(condition) => {
+                        matches_operators(&values, operators)?
+                    }
+                    expected => equals_any(&values, expected),
+                }
+            }
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn is_included(value: &mongodb::bson::Bson) -> Result<bool, Box<dyn std::error::Error>> {
+    match value {
+        mongodb::bson::Bson::Boolean(b) => Ok(*b),
+        o => as_number(o)
+            .map(|n| n != 0.0)
+            .ok_or_else(|| unsupported("projections with expressions")),
+    }
+}
+
+/// Keep the fields at the paths and drop the others, or the other way around.
+fn project_fields(
+    document: &mongodb::bson::Document,
+    paths: &[Vec<&str>],
+    inclusion: bool,
+) -> mongodb::bson::Document {
+    let mut projected = mongodb::bson::Document::new();
+    for (key, value) in document {
+        let nested = paths
+            .iter()
+            .filter(|path| path[0] == key.as_str())
+            .map(|path| path[1..].to_vec())
+            .collect::<Vec<_>>();
+        let value = if nested.is_empty() {
+            if inclusion {
+                continue;
+            }
+            value.clone()
+        } else if nested.iter().any(Vec::is_empty) {
+            if !inclusion {
+                continue;
+            }
+            value.clone()
+        } else {
+            match value {
+                mongodb::bson::Bson::Document(document) => {
+                    mongodb::bson::Bson::Document(project_fields(document, &nested, inclusion))
+                }
+                mongodb::bson::Bson::Array(array) => mongodb::bson::Bson::Array(
+                    array
+                        .iter()
+                        .filter_map(|element| match element {
+                            mongodb::bson::Bson::Document(document) => {
+                                Some(mongodb::bson::Bson::Document(project_fields(
+                                    document, &nested, inclusion,
+                                )))
+                            }
+                            _ if inclusion => None,
+                            o => Some(o.clone()),
+                        })
+                        .collect(),
+                ),
+                _ if inclusion => continue,
+                o => o.clone(),
+            }
+        };
+        projected.insert(key.clone(), value);
+    }
+    projected
+}
+
+fn project(
+    document: &mongodb::bson::Document,
+    projection: &mongodb::bson::Document,
+) -> Result<mongodb::bson::Document, Box<dyn std::error::Error>> {
+    let mut paths = vec![];
+    let mut inclusion = None;
+    let mut include_id = None;
+    for (path, value) in projection {
+        let included = is_included(value)?;
+        if path == "_id" {
+            include_id = Some(included);
+            continue;
+        }
+        if inclusion.is_some_and(|inclusion| inclusion != included) {
+            return Err("A projection cannot mix including and excluding fields".into());
+        }
+        inclusion = Some(included);
+        paths.push(path.split('.').collect::<Vec<_>>());
+    }
+    // A projection of only the _id includes or excludes it.
+    let inclusion = inclusion.unwrap_or(include_id == Some(true));
+    // The _id is kept unless it is excluded explicitly.
+    if inclusion == (include_id != Some(false)) {
+        paths.push(vec!["_id"]);
+    }
+    Ok(project_fields(document, &paths, inclusion))
+}
+
+fn skip_and_limit(
+    documents: Vec<mongodb::bson::Document>,
+    skip: Option<u64>,
+    limit: Option<i64>,
+) -> Vec<mongodb::bson::Document> {
+    let documents = documents.into_iter().skip(skip.unwrap_or(0) as usize);
+    // A negative limit means the same as its absolute value, in a single batch.
+    match limit.map(i64::unsigned_abs) {
+        Some(limit) if limit > 0 => documents.take(limit as usize).collect(),
+        _ => documents.collect(),
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn find(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+        options: mongodb::options::FindOptions,
+    ) -> Result<Documents, Box<dyn std::error::Error>> {
+        let mut documents = self.matching(database_name, collection_name, filter.as_ref())?;
+        if let Some(sort) = &options.sort {
+            documents.sort_by(|a, b| sort_order(a, b, sort));
+        }
+        let mut documents = skip_and_limit(documents, options.skip, options.limit);
+        if let Some(projection) = &options.projection {
+            documents = documents
+                .iter()
+                .map(|document| project(document, projection))
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(into_documents(documents))
+    }
+
+    fn find_one(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+        options: mongodb::options::FindOneOptions,
+    ) -> Result<Option<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+        let options = mongodb::options::FindOptions::builder()
+            .projection(options.projection)
+            .sort(options.sort)
+            .skip(options.skip)
+            .limit(1)
+            .build();
+        self.find(database_name, collection_name, filter, options)?
+            .next()
+            .transpose()
+    }
+
+    fn count(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(self
+            .matching(database_name, collection_name, filter.as_ref())?
+            .len() as u64)
+    }
+
+    fn aggregate(
+        &self,
+        database_name: &str,
+        collection_name: Option<&str>,
+        stages: Vec<mongodb::bson::Document>,
+        _: mongodb::options::AggregateOptions,
+    ) -> Result<Documents, Box<dyn std::error::Error>> {
+        let mut documents = collection_name
+            .map(|collection_name| self.documents(database_name, collection_name))
+            .unwrap_or_default();
+        for (index, stage) in stages.iter().enumerate() {
+            let (name, argument) = match (stage.len(), stage.iter().next()) {
+                (1, Some(field)) => field,
+                _ => return Err("A stage must have exactly one field".into()),
+            };
+            documents = match (name.as_str(), argument) {
+                ("$documents", mongodb::bson::Bson::Array(array))
+                    if index == 0 && collection_name.is_none() =>
+                {
+                    array
+                        .iter()
+                        .map(|value| {
+                            value
+                                .as_document()
+                                .cloned()
+                                .ok_or("$documents needs an array of documents")
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                ("$match", mongodb::bson::Bson::Document(filter)) => {
+                    let mut matching = vec![];
+                    for document in documents {
+                        if matches_filter(&document, filter)? {
+                            matching.push(document);
+                        }
+                    }
+                    matching
+                }
+                ("$project", mongodb::bson::Bson::Document(projection)) => documents
+                    .iter()
+                    .map(|document| project(document, projection))
+                    .collect::<Result<_, _>>()?,
+                ("$sort", mongodb::bson::Bson::Document(sort)) => {
+                    documents.sort_by(|a, b| sort_order(a, b, sort));
+                    documents
+                }
+                ("$skip", skip) if is_number(skip) => {
+                    skip_and_limit(documents, as_number(skip).map(|n| n as u64), None)
+                }
+                ("$limit", limit) if is_number(limit) => {
+                    skip_and_limit(documents, None, as_number(limit).map(|n| n as i64))
+                }
+                ("$count", mongodb::bson::Bson::String(field)) => {
+                    let mut count = mongodb::bson::Document::new();
+                    count.insert(field.clone(), documents.len() as i32);
+                    vec![count]
+                }
+                ("$out", mongodb::bson::Bson::String(output)) if index == stages.len() - 1 => {
+                    self.databases
+                        .borrow_mut()
+                        .entry(database_name.to_string())
+                        .or_default()
+                        .insert(output.clone(), documents);
+                    vec![]
+                }
+                (name, _) => return Err(unsupported(&format!("this {} stage", name))),
+            };
+        }
+        Ok(into_documents(documents))
+    }
+
+    fn insert(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        documents: Vec<mongodb::bson::Document>,
+    ) -> Result<Vec<mongodb::bson::Bson>, Box<dyn std::error::Error>> {
+        let mut databases = self.databases.borrow_mut();
+        let collection = databases
+            .entry(database_name.to_string())
+            .or_default()
+            .entry(collection_name.to_string())
+            .or_default();
+        let mut inserted_ids = vec![];
+        for document in documents {
+            // The driver adds an ObjectId as the first field of documents without an _id.
+            let document = if document.contains_key("_id") {
+                document
+            } else {
+                let mut with_id =
+                    mongodb::bson::doc! { "_id": mongodb::bson::oid::ObjectId::new() };
+                with_id.extend(document);
+                with_id
+            };
+            let id = document.get("_id").cloned().unwrap_or_default();
+            if collection
+                .iter()
+                .any(|existing| existing.get("_id").is_some_and(|e| bson_equal(e, &id)))
+            {
+                return Err(format!("Duplicate _id {}", id).into());
+            }
+            inserted_ids.push(id);
+            collection.push(document);
+        }
+        Ok(inserted_ids)
+    }
+
+    fn delete(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: mongodb::bson::Document,
+        many: bool,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut databases = self.databases.borrow_mut();
+        let collection = match databases
+            .get_mut(database_name)
+            .and_then(|collections| collections.get_mut(collection_name))
+        {
+            Some(collection) => collection,
+            None => return Ok(0),
+        };
+        let mut deleted = vec![];
+        for (index, document) in collection.iter().enumerate() {
+            if (many || deleted.is_empty()) && matches_filter(document, &filter)? {
+                deleted.push(index);
+            }
+        }
+        for index in deleted.iter().rev() {
+            collection.remove(*index);
+        }
+        Ok(deleted.len() as u64)
+    }
+
+    fn list_databases(
+        &self,
+    ) -> Result<Vec<mongodb::results::DatabaseSpecification>, Box<dyn std::error::Error>> {
+        let mut specifications = vec![];
+        for (name, collections) in self.databases.borrow().iter() {
+            let mut size = 0;
+            for document in collections.values().flatten() {
+                let mut bytes = vec![];
+                document.to_writer(&mut bytes)?;
+                size += bytes.len() as i64;
+            }
+            specifications.push(mongodb::bson::from_document(mongodb::bson::doc! {
+                "name": name,
+                "sizeOnDisk": size,
+                "empty": collections.values().all(Vec::is_empty),
+            })?);
+        }
+        Ok(specifications)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, Document};
+
+    use super::*;
+    use crate::app::seeded_backend;
+
+    fn ids(backend: &MemoryBackend, filter: Document) -> Vec<i32> {
+        backend
+            .find("test", "items", Some(filter), Default::default())
+            .unwrap()
+            .map(|d| d.unwrap().get_i32("_id").unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn matches_equality_and_dotted_paths() {
+        let backend = seeded_backend();
+        assert_eq!(ids(&backend, doc! { "name": "bread" }), vec![2]);
+        assert_eq!(ids(&backend, doc! { "price": 3.5 }), vec![1]);
+        // Numbers are equal regardless of their type.
+        assert_eq!(ids(&backend, doc! { "price": 10 }), vec![3]);
+        assert_eq!(ids(&backend, doc! { "stock.count": 12 }), vec![4]);
+        // An array matches any of its elements.
+        assert_eq!(ids(&backend, doc! { "tags": "fruit" }), vec![1, 3]);
+        assert_eq!(ids(&backend, doc! { "tags": ["fruit"] }), vec![3]);
+        assert_eq!(ids(&backend, doc! { "price": null }), vec![4]);
+    }
+
+    #[test]
+    fn matches_operators() {
+        let backend = seeded_backend();
+        assert_eq!(ids(&backend, doc! { "price": { "$gt": 3 } }), vec![1, 3]);
+        assert_eq!(
+            ids(&backend, doc! { "price": { "$gte": 2, "$lt": 10 } }),
+            vec![1, 2]
+        );
+        assert_eq!(ids(&backend, doc! { "price": { "$ne": 2 } }), vec![1, 3, 4]);
+        assert_eq!(
+            ids(&backend, doc! { "_id": { "$in": [2, 4, 5] } }),
+            vec![2, 4]
+        );
+        assert_eq!(
+            ids(&backend, doc! { "_id": { "$nin": [2, 4] } }),
+            vec![1, 3]
+        );
+        assert_eq!(
+            ids(&backend, doc! { "stock": { "$exists": true } }),
+            vec![2, 4]
+        );
+        assert_eq!(ids(&backend, doc! { "tags": { "$size": 2 } }), vec![1]);
+        assert_eq!(
+            ids(
+                &backend,
+                doc! { "name": { "$regex": "^d", "$options": "i" } }
+            ),
+            vec![4]
+        );
+        assert_eq!(
+            ids(&backend, doc! { "price": { "$not": { "$gt": 3 } } }),
+            vec![2, 4]
+        );
+        assert_eq!(
+            ids(
+                &backend,
+                doc! { "$or": [{ "_id": 1 }, { "stock.count": 0 }] }
+            ),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids(
+                &backend,
+                doc! { "$and": [{ "tags": "fruit" }, { "price": { "$lt": 5 } }] }
+            ),
+            vec![1]
+        );
+        assert_eq!(
+            ids(&backend, doc! { "$nor": [{ "tags": "fruit" }] }),
+            vec![2, 4]
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_operators() {
+        let error = seeded_backend()
+            .count("test", "items", Some(doc! { "tags": { "$elemMatch": {} } }))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The in-memory backend does not support the $elemMatch operator"
+        );
+    }
+
+    #[test]
+    fn sorts_limits_and_projects() {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "price": -1 })
+            .limit(3)
+            .projection(doc! { "name": 1, "stock.count": 1, "_id": 0 })
+            .build();
+        let documents = seeded_backend()
+            .find("test", "items", None, options)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            documents,
+            vec![
+                doc! { "name": "cherry" },
+                doc! { "name": "apple" },
+                doc! { "name": "bread", "stock": { "count": 0 } },
+            ]
+        );
+    }
+
+    #[test]
+    fn excludes_fields() {
+        let options = mongodb::options::FindOneOptions::builder()
+            .projection(doc! { "tags": 0, "price": 0 })
+            .build();
+        let document = seeded_backend()
+            .find_one("test", "items", Some(doc! { "_id": 1 }), options)
+            .unwrap();
+        assert_eq!(document, Some(doc! { "_id": 1, "name": "apple" }));
+    }
+
+    #[test]
+    fn inserts_with_generated_ids_and_rejects_duplicates() {
+        let backend = seeded_backend();
+        let ids = backend
+            .insert("test", "items", vec![doc! { "name": "egg" }])
+            .unwrap();
+        assert!(matches!(ids.as_slice(), [mongodb::bson::Bson::ObjectId(_)]));
+        assert_eq!(
+            backend.documents("test", "items")[4].keys().next().unwrap(),
+            "_id"
+        );
+        assert!(backend
+            .insert("test", "items", vec![doc! { "_id": 1 }])
+            .is_err());
+    }
+
+    #[test]
+    fn deletes_one_or_many() {
+        let backend = seeded_backend();
+        let filter = doc! { "tags": "fruit" };
+        assert_eq!(
+            backend
+                .delete("test", "items", filter.clone(), false)
+                .unwrap(),
+            1
+        );
+        assert_eq!(ids(&backend, doc! {}), vec![2, 3, 4]);
+        assert_eq!(backend.delete("test", "items", doc! {}, true).unwrap(), 3);
+        assert_eq!(backend.delete("test", "missing", filter, true).unwrap(), 0);
+    }
+
+    #[test]
+    fn runs_pipelines() {
+        let backend = seeded_backend();
+        let stages = vec![
+            doc! { "$match": { "price": { "$exists": true } } },
+            doc! { "$sort": { "name": -1 } },
+            doc! { "$skip": 1 },
+            doc! { "$project": { "name": 1 } },
+            doc! { "$limit": 1 },
+        ];
+        let documents = backend
+            .aggregate("test", Some("items"), stages, Default::default())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(documents, vec![doc! { "_id": 2, "name": "bread" }]);
+
+        let stages = vec![
+            doc! { "$documents": [{ "a": 1 }, { "a": 2 }] },
+            doc! { "$count": "total" },
+            doc! { "$out": "totals" },
+        ];
+        let mut documents = backend
+            .aggregate("test", None, stages, Default::default())
+            .unwrap();
+        assert!(documents.next().is_none());
+        assert_eq!(
+            backend.documents("test", "totals"),
+            vec![doc! { "total": 2 }]
+        );
+    }
+
+    #[test]
+    fn lists_databases() {
+        let backend = seeded_backend();
+        backend.insert("other", "empty", vec![]).unwrap();
+        let databases = backend.list_databases().unwrap();
+        assert_eq!(
+            databases
+                .iter()
+                .map(|d| (d.name.as_str(), d.empty))
+                .collect::<Vec<_>>(),
+            vec![("other", true), ("test", false)]
+        );
+        assert!(databases[1].size_on_disk > 0);
+    }
+}
@@ -0,0 +1,68 @@
+//! The operations magg performs on a deployment. [`MongoBackend`] runs them through the
+//! driver, [`MemoryBackend`] keeps the collections in memory so the commands can be tested
+//! without a server.
+
+mod memory;
+mod mongo;
+
+pub use memory::MemoryBackend;
+pub use mongo::MongoBackend;
+
+/// The documents returned by a query, fetched lazily by the driver.
+pub type Documents =
+    Box<dyn Iterator<Item = Result<mongodb::bson::Document, Box<dyn std::error::Error>>>>;
+
+pub trait Backend {
+    fn find(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+        options: mongodb::options::FindOptions,
+    ) -> Result<Documents, Box<dyn std::error::Error>>;
+
+    fn find_one(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+        options: mongodb::options::FindOneOptions,
+    ) -> Result<Option<mongodb::bson::Document>, Box<dyn std::error::Error>>;
+
+    fn count(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+    ) -> Result<u64, Box<dyn std::error::Error>>;
+
+    /// Run the pipeline against the collection, or against the database if there is none.
+    fn aggregate(
+        &self,
+        database_name: &str,
+        collection_name: Option<&str>,
+        stages: Vec<mongodb::bson::Document>,
+        options: mongodb::options::AggregateOptions,
+    ) -> Result<Documents, Box<dyn std::error::Error>>;
+
+    /// Returns the `_id`s of the inserted documents, in the order they were given.
+    fn insert(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        documents: Vec<mongodb::bson::Document>,
+    ) -> Result<Vec<mongodb::bson::Bson>, Box<dyn std::error::Error>>;
+
+    /// Delete the first or all of the matching documents. Returns the number deleted.
+    fn delete(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: mongodb::bson::Document,
+        many: bool,
+    ) -> Result<u64, Box<dyn std::error::Error>>;
+
+    fn list_databases(
+        &self,
+    ) -> Result<Vec<mongodb::results::DatabaseSpecification>, Box<dyn std::error::Error>>;
+}
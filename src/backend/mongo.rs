@@ -0,0 +1,133 @@
+use super::{Backend, Documents};
+use crate::shared::connect;
+
+/// Runs the operations on a deployment through the driver.
+#[derive(Clone, Debug)]
+pub struct MongoBackend {
+    client: mongodb::sync::Client,
+}
+
+impl MongoBackend {
+    pub fn new(client: mongodb::sync::Client) -> Self {
+        MongoBackend { client }
+    }
+
+    /// Connect to the deployment, reusing an open client. See [`connect`].
+    pub fn connect(connection_uri: &str) -> mongodb::error::Result<Self> {
+        Ok(MongoBackend::new(connect(connection_uri)?))
+    }
+
+    pub fn client(&self) -> &mongodb::sync::Client {
+        &self.client
+    }
+
+    fn collection(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+    ) -> mongodb::sync::Collection<mongodb::bson::Document> {
+        self.client
+            .database(database_name)
+            .collection::<mongodb::bson::Document>(collection_name)
+    }
+}
+
+fn into_documents(cursor: mongodb::sync::Cursor<mongodb::bson::Document>) -> Documents {
+    Box::new(cursor.map(|result| result.map_err(|e| e.into())))
+}
+
+impl Backend for MongoBackend {
+    fn find(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+        options: mongodb::options::FindOptions,
+    ) -> Result<Documents, Box<dyn std::error::Error>> {
+        let cursor = self
+            .collection(database_name, collection_name)
+            .find(filter, options)?;
+        Ok(into_documents(cursor))
+    }
+
+    fn find_one(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+        options: mongodb::options::FindOneOptions,
+    ) -> Result<Option<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+        Ok(self
+            .collection(database_name, collection_name)
+            .find_one(filter, options)?)
+    }
+
+    fn count(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: Option<mongodb::bson::Document>,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(self
+            .collection(database_name, collection_name)
+            .count_documents(filter, None)?)
+    }
+
+    fn aggregate(
+        &self,
+        database_name: &str,
+        collection_name: Option<&str>,
+        stages: Vec<mongodb::bson::Document>,
+        options: mongodb::options::AggregateOptions,
+    ) -> Result<Documents, Box<dyn std::error::Error>> {
+        let cursor = match collection_name {
+            Some(collection_name) => self
+                .collection(database_name, collection_name)
+                .aggregate(stages, options)?,
+            None => self
+                .client
+                .database(database_name)
+                .aggregate(stages, options)?,
+        };
+        Ok(into_documents(cursor))
+    }
+
+    fn insert(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        documents: Vec<mongodb::bson::Document>,
+    ) -> Result<Vec<mongodb::bson::Bson>, Box<dyn std::error::Error>> {
+        let collection = self.collection(database_name, collection_name);
+        if let [document] = documents.as_slice() {
+            return Ok(vec![collection.insert_one(document, None)?.inserted_id]);
+        }
+        let count = documents.len();
+        let mut inserted_ids = collection.insert_many(documents, None)?.inserted_ids;
+        Ok((0..count)
+            .filter_map(|index| inserted_ids.remove(&index))
+            .collect())
+    }
+
+    fn delete(
+        &self,
+        database_name: &str,
+        collection_name: &str,
+        filter: mongodb::bson::Document,
+        many: bool,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let collection = self.collection(database_name, collection_name);
+        let result = if many {
+            collection.delete_many(filter, None)?
+        } else {
+            collection.delete_one(filter, None)?
+        };
+        Ok(result.deleted_count)
+    }
+
+    fn list_databases(
+        &self,
+    ) -> Result<Vec<mongodb::results::DatabaseSpecification>, Box<dyn std::error::Error>> {
+        Ok(self.client.list_databases(None, None)?)
+    }
+}
@@ -0,0 +1,195 @@
+//! Typed versions of the find, count, aggregate, create and delete subcommands, for running
+//! them without going through the command line.
+
+use crate::backend::{Backend, Documents};
+use crate::shared::{AggregationOptions, Config, Pipeline, Query};
+
+/// Find the documents that match a filter.
+#[derive(Debug, Clone, Default)]
+pub struct Find {
+    pub filter: Option<mongodb::bson::Document>,
+    pub projection: Option<mongodb::bson::Document>,
+    pub sort: Option<mongodb::bson::Document>,
+    pub limit: Option<i64>,
+}
+
+impl Find {
+    pub fn from_query(query: &Query) -> Self {
+        Find {
+            filter: Some(query.filter.clone()),
+            projection: query.projection.clone(),
+            sort: query.sort.clone(),
+            limit: query.limit,
+        }
+    }
+
+    pub fn run(
+        &self,
+        backend: &dyn Backend,
+        database_name: &str,
+        collection_name: &str,
+    ) -> Result<Documents, Box<dyn std::error::Error>> {
+        let options = mongodb::options::FindOptions::builder()
+            .limit(self.limit)
+            .projection(self.projection.clone())
+            .sort(self.sort.clone())
+            .build();
+        backend.find(database_name, collection_name, self.filter.clone(), options)
+    }
+
+    /// The first matching document. The limit is ignored.
+    pub fn run_one(
+        &self,
+        backend: &dyn Backend,
+        database_name: &str,
+        collection_name: &str,
+    ) -> Result<Option<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+        let options = mongodb::options::FindOneOptions::builder()
+            .projection(self.projection.clone())
+            .sort(self.sort.clone())
+            .build();
+        backend.find_one(database_name, collection_name, self.filter.clone(), options)
+    }
+}
+
+/// Count the documents that match a filter.
+#[derive(Debug, Clone, Default)]
+pub struct Count {
+    pub filter: Option<mongodb::bson::Document>,
+}
+
+impl Count {
+    pub fn run(
+        &self,
+        backend: &dyn Backend,
+        database_name: &str,
+        collection_name: &str,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        backend.count(database_name, collection_name, self.filter.clone())
+    }
+}
+
+/// What an aggregation runs against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregateTarget {
+    Collection(String),
+    /// Database-level stages such as `$currentOp` or `$documents`.
+    Database,
+}
+
+/// Run an aggregation pipeline.
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+    pub stages: Vec<mongodb::bson::Document>,
+    pub options: AggregationOptions,
+    pub target: AggregateTarget,
+}
+
+impl Aggregate {
+    /// The saved pipeline with its stages file read and its fragments expanded. It runs
+    /// against its own collection if it names one, otherwise against the configured one.
+    pub fn from_pipeline(
+        config: &Config,
+        pipeline: &Pipeline,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let target = if pipeline.database_level {
+            AggregateTarget::Database
+        } else {
+            AggregateTarget::Collection(
+                pipeline
+                    .collection_name
+                    .clone()
+                    .unwrap_or_else(|| config.collection_name.clone()),
+            )
+        };
+        Ok(Aggregate {
+            stages: config.pipeline_stages(pipeline)?,
+            options: pipeline.options.clone(),
+            target,
+        })
+    }
+
+    /// The namespace written to by a final `$out` or `$merge` stage.
+    pub fn output_namespace(&self, database_name: &str) -> Option<(String, String)> {
+        let stage = self.stages.last()?;
+        let target = match stage.get("$out") {
+            Some(out) => out,
+            None => match stage.get("$merge")? {
+                mongodb::bson::Bson::Document(merge) => merge.get("into")?,
+                into => into,
+            },
+        };
+        match target {
+            mongodb::bson::Bson::String(collection_name) => {
+                Some((database_name.to_string(), collection_name.clone()))
+            }
+            mongodb::bson::Bson::Document(namespace) => Some((
+                namespace.get_str("db").unwrap_or(database_name).to_string(),
+                namespace.get_str("coll").ok()?.to_string(),
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn run(
+        &self,
+        backend: &dyn Backend,
+        database_name: &str,
+    ) -> Result<Documents, Box<dyn std::error::Error>> {
+        let collection_name = match &self.target {
+            AggregateTarget::Collection(collection_name) => Some(collection_name.as_str()),
+            AggregateTarget::Database => None,
+        };
+        backend.aggregate(
+            database_name,
+            collection_name,
+            self.stages.clone(),
+            self.options.to_driver_options()?,
+        )
+    }
+}
+
+/// Insert documents.
+#[derive(Debug, Clone, Default)]
+pub struct Create {
+    pub documents: Vec<mongodb::bson::Document>,
+}
+
+impl Create {
+    /// Returns the `_id`s of the inserted documents.
+    pub fn run(
+        &self,
+        backend: &dyn Backend,
+        database_name: &str,
+        collection_name: &str,
+    ) -> Result<Vec<mongodb::bson::Bson>, Box<dyn std::error::Error>> {
+        if self.documents.is_empty() {
+            return Err("There are no documents to insert".into());
+        }
+        backend.insert(database_name, collection_name, self.documents.clone())
+    }
+}
+
+/// Delete the first or all of the documents that match a filter.
+#[derive(Debug, Clone, Default)]
+pub struct Delete {
+    pub filter: mongodb::bson::Document,
+    pub many: bool,
+}
+
+impl Delete {
+    /// Returns the number of deleted documents.
+    pub fn run(
+        &self,
+        backend: &dyn Backend,
+        database_name: &str,
+        collection_name: &str,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        backend.delete(
+            database_name,
+            collection_name,
+            self.filter.clone(),
+            self.many,
+        )
+    }
+}
@@ -0,0 +1,20 @@
+//! Perform queries and updates on MongoDB collections.
+//!
+//! The `magg` binary is a thin wrapper over [`app`]. The [`commands`] can be used directly,
+//! against any [`backend`], with the configuration, conversion and output helpers of [`shared`].
+
+pub mod app;
+pub mod backend;
+pub mod commands;
+pub mod history;
+pub mod logging;
+pub mod shared;
+pub mod stats;
+pub mod validation;
+
+pub use backend::{Backend, MemoryBackend, MongoBackend};
+pub use commands::{Aggregate, AggregateTarget, Count, Create, Delete, Find};
+pub use shared::{
+    connect, convert_json_value_to_bson_document, AggregationOptions, Config, OutputFormat,
+    Pipeline, Query,
+};
@@ -1,13 +1,32 @@
-mod app;
-mod shared;
-
-use app::{main_app, to_handler};
-use shared::Config;
+use clap::Parser;
+use magg::app::{to_handler, to_standalone_handler, Magg};
+use magg::shared::Config;
+use magg::{history, logging, stats};
 
 // TODO: Implement our own error type
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let matches = main_app().get_matches();
-    let config = Config::from_matches(&matches)?;
-    to_handler(matches, config)?;
-    Ok(())
+    let args = std::env::args().collect::<Vec<_>>();
+    let magg = Magg::parse_from(&args);
+    logging::init(
+        magg.global.verbose as u64,
+        magg.global.log_format,
+        magg.global.redact,
+    );
+    if magg.global.stats {
+        stats::enable();
+    }
+    // Not recorded, the history subcommand records the commands it re-runs itself.
+    if let Some(result) = to_standalone_handler(&magg, &mut std::io::stdout().lock()) {
+        let _ = stats::print_summary(&mut std::io::stderr());
+        return result;
+    }
+    let config = Config::from_args(&magg.global)?;
+    let recorded = magg.command.is_some();
+    let result = to_handler(magg, config, &mut std::io::stdout().lock());
+    let _ = stats::print_summary(&mut std::io::stderr());
+    if recorded {
+        // Failing to write the history must not hide the result of the command.
+        let _ = history::record(&args[1..], result.is_ok());
+    }
+    result
 }
@@ -1,98 +1,455 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
 use serde::{Deserialize, Serialize};
 
-pub mod keywords {
-    pub const INPUT_FILTER: &'static str = "input-filter";
-    pub const CONNECTION_URI: &'static str = "connection-uri";
-    pub const DATABASE_NAME: &'static str = "database-name";
-    pub const COLLECTION_NAME: &'static str = "collection-name";
-    pub const PIPELINE: &'static str = "pipeline";
-    pub const INPUT_DOCUMENTS: &'static str = "input-documents";
-    pub const INPUT_FILE: &'static str = "input-file";
-    pub const PROJECT: &'static str = "project";
-    pub const PIPELINE_INDEX: &'static str = "pipeline-index";
-    pub const LIST: &'static str = "list";
-    pub const LIMIT: &'static str = "limit";
-    pub const CONFIG_FILE: &'static str = "config-file";
-}
-
-pub enum MongoDbCommand {
-    Create,
-    Aggregate,
-    FindMany,
-    FindOne,
-    Count,
-    DeleteMany,
-    DeleteOne,
-    ListDatabases,
-}
-
-impl MongoDbCommand {
-    pub fn to_str(self) -> &'static str {
-        match self {
-            MongoDbCommand::Create => "create",
-            MongoDbCommand::Aggregate => "aggregate",
-            MongoDbCommand::FindMany => "find-many",
-            MongoDbCommand::FindOne => "find-one",
-            MongoDbCommand::Count => "count",
-            MongoDbCommand::DeleteOne => "delete-one",
-            MongoDbCommand::DeleteMany => "delete-many",
-            MongoDbCommand::ListDatabases => "list-databases",
-        }
-    }
-}
+use mongodb::event::command::{
+    CommandEventHandler, CommandFailedEvent, CommandStartedEvent, CommandSucceededEvent,
+};
+
+use crate::{logging, stats};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum PipelineDescription {
     OneLine(String),
     MultiLine(Vec<String>),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl PipelineDescription {
+    /// Descriptions spanning several lines are kept as one string per line.
+    pub fn from_text(text: &str) -> Self {
+        if text.contains('\n') {
+            PipelineDescription::MultiLine(text.lines().map(String::from).collect())
+        } else {
+            PipelineDescription::OneLine(text.to_string())
+        }
+    }
+
+    pub fn text(&self) -> String {
+        match self {
+            PipelineDescription::OneLine(s) => s.clone(),
+            PipelineDescription::MultiLine(v) => v.join("\n"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Pipeline {
     pub name: String,
     pub description: PipelineDescription,
+    #[serde(default)]
     pub stages: Vec<mongodb::bson::document::Document>,
+    /// A file containing the stages, relative to the configuration file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stages_file: Option<String>,
+    #[serde(default, skip_serializing_if = "AggregationOptions::is_empty")]
+    pub options: AggregationOptions,
+    /// The collection the pipeline runs on instead of the configured one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection_name: Option<String>,
+    /// Run the pipeline against the database instead of a collection, e.g. for `$currentOp`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub database_level: bool,
+}
+
+/// The options of an aggregation, given in a saved pipeline or on the command line.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregationOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_disk_use: Option<bool>,
+    #[serde(default, rename = "maxTimeMS", skip_serializing_if = "Option::is_none")]
+    pub max_time_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collation: Option<mongodb::bson::Document>,
+    /// The name or the key pattern of the index to use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<mongodb::bson::Bson>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default, rename = "let", skip_serializing_if = "Option::is_none")]
+    pub let_vars: Option<mongodb::bson::Document>,
+}
+
+impl AggregationOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == AggregationOptions::default()
+    }
+
+    /// The options set in `overrides` replace these.
+    pub fn merge(self, overrides: AggregationOptions) -> Self {
+        AggregationOptions {
+            allow_disk_use: overrides.allow_disk_use.or(self.allow_disk_use),
+            max_time_ms: overrides.max_time_ms.or(self.max_time_ms),
+            batch_size: overrides.batch_size.or(self.batch_size),
+            collation: overrides.collation.or(self.collation),
+            hint: overrides.hint.or(self.hint),
+            comment: overrides.comment.or(self.comment),
+            let_vars: overrides.let_vars.or(self.let_vars),
+        }
+    }
+
+    pub fn to_driver_options(
+        &self,
+    ) -> Result<mongodb::options::AggregateOptions, Box<dyn std::error::Error>> {
+        let collation = self
+            .collation
+            .clone()
+            .map(mongodb::bson::from_document::<mongodb::options::Collation>)
+            .transpose()?;
+        let hint = match &self.hint {
+            None => None,
+            Some(mongodb::bson::Bson::String(name)) => {
+                Some(mongodb::options::Hint::Name(name.clone()))
+            }
+            Some(mongodb::bson::Bson::Document(keys)) => {
+                Some(mongodb::options::Hint::Keys(keys.clone()))
+            }
+            Some(_) => return Err("'hint' must be an index name or a key pattern".into()),
+        };
+        Ok(mongodb::options::AggregateOptions::builder()
+            .allow_disk_use(self.allow_disk_use)
+            .max_time(self.max_time_ms.map(std::time::Duration::from_millis))
+            .batch_size(self.batch_size)
+            .collation(collation)
+            .hint(hint)
+            .comment(self.comment.clone())
+            .let_vars(self.let_vars.clone())
+            .build())
+    }
+}
+
+/// A saved `find` query, run with `find-many --query-name`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Query {
+    pub name: String,
+    pub description: PipelineDescription,
+    #[serde(default)]
+    pub filter: mongodb::bson::document::Document,
+    #[serde(default)]
+    pub projection: Option<mongodb::bson::document::Document>,
+    #[serde(default)]
+    pub sort: Option<mongodb::bson::document::Document>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// The arguments of the main command. They may be given before or after the subcommand.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct GlobalArgs {
+    /// The connection string of the deployment, e.g. mongodb://localhost:27017
+    #[arg(long, global = true)]
+    pub connection_uri: Option<String>,
+    /// The database the subcommands run against
+    #[arg(long, global = true)]
+    pub database_name: Option<String>,
+    /// The collection the subcommands run against
+    #[arg(long, global = true)]
+    pub collection_name: Option<String>,
+    /// A JSON file with the connection, the saved pipelines and the saved queries. It takes
+    /// precedence over the three arguments above
+    #[arg(long, global = true)]
+    pub config_file: Option<String>,
+    /// How the resulting documents are printed
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Document)]
+    pub output_format: OutputFormat,
+    /// Log the configuration, the connection and the commands sent to the server to stderr.
+    /// Pass it twice to also log the commands and the replies
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// How the logs are written
+    #[arg(long, global = true, value_enum, default_value_t = logging::LogFormat::Text)]
+    pub log_format: logging::LogFormat,
+    /// Print the time spent connecting, on the server and on the output, the documents returned
+    /// or affected and the bytes received to stderr
+    #[arg(long, global = true)]
+    pub stats: bool,
+    /// Replace the values in the logged commands and replies with "?"
+    #[arg(long, global = true)]
+    pub redact: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub connection_uri: String,
     pub collection_name: String,
     pub database_name: String,
     pub pipelines: Vec<Pipeline>,
+    #[serde(default)]
+    pub queries: Vec<Query>,
+    /// Named lists of stages that pipelines include with `{"$include": "<name>"}`.
+    #[serde(default)]
+    pub fragments: HashMap<String, Vec<mongodb::bson::document::Document>>,
+    /// The file the configuration was read from, if any.
+    #[serde(skip)]
+    pub path: Option<std::path::PathBuf>,
 }
 
 impl Config {
-    pub fn from_matches(matches: &clap::ArgMatches) -> Result<Self, Box<dyn std::error::Error>> {
-        let config = if let Some(config_file) = matches.value_of(keywords::CONFIG_FILE) {
-            let file = std::fs::File::open(config_file)?;
-            serde_json::from_reader(file)?
+    /// A configuration without saved pipelines, queries or fragments.
+    pub fn new(connection_uri: &str, database_name: &str, collection_name: &str) -> Self {
+        Config {
+            connection_uri: connection_uri.into(),
+            database_name: database_name.into(),
+            collection_name: collection_name.into(),
+            pipelines: vec![],
+            queries: vec![],
+            fragments: HashMap::new(),
+            path: None,
+        }
+    }
+
+    pub fn from_args(args: &GlobalArgs) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = if let Some(config_file) = &args.config_file {
+            Config::from_file(config_file)?
         } else {
             match (
-                matches.value_of(keywords::CONNECTION_URI),
-                matches.value_of(keywords::DATABASE_NAME),
-                matches.value_of(keywords::COLLECTION_NAME),
+                &args.connection_uri,
+                &args.database_name,
+                &args.collection_name,
             ) {
-                (Some(connection_uri), Some(database_name), Some(collection_name)) => Config {
-                    connection_uri: connection_uri.into(),
-                    database_name: database_name.into(),
-                    collection_name: collection_name.into(),
-                    pipelines: vec![],
-                },
+                (Some(connection_uri), Some(database_name), Some(collection_name)) => {
+                    Config::new(connection_uri, database_name, collection_name)
+                }
                 _ => {
                     return Err("Please provide the connection-uri, database-name and collection-name by passing them as arguments or through config-file".into());
                 }
             }
         };
+        logging::log(
+            logging::Level::Info,
+            "resolved the configuration",
+            vec![
+                (
+                    "source",
+                    match &config.path {
+                        Some(path) => path.display().to_string().into(),
+                        None => "arguments".into(),
+                    },
+                ),
+                ("uri", logging::redact_uri(&config.connection_uri).into()),
+                ("database", config.database_name.clone().into()),
+                ("collection", config.collection_name.clone().into()),
+                ("pipelines", config.pipelines.len().into()),
+                ("queries", config.queries.len().into()),
+            ],
+        );
+        Ok(config)
+    }
+
+    /// The configuration of a command run from the shell or the history. The connection is
+    /// kept unless the command names its own, the database and collection names override it.
+    pub fn with_overrides(self, args: &GlobalArgs) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config = if args.config_file.is_some() || args.connection_uri.is_some() {
+            Config::from_args(args)?
+        } else {
+            self
+        };
+        if let Some(database_name) = &args.database_name {
+            config.database_name = database_name.clone();
+        }
+        if let Some(collection_name) = &args.collection_name {
+            config.collection_name = collection_name.clone();
+        }
+        Ok(config)
+    }
+
+    pub fn from_file<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path.as_ref())?;
+        let mut config: Config = serde_json::from_reader(file)?;
+        config.path = Some(path.as_ref().to_path_buf());
         Ok(config)
     }
+
+    /// Add the pipeline to the configuration file, replacing the one with the same name when
+    /// `force` is set. The file is rewritten with its key order and indentation preserved.
+    pub fn save_pipeline(
+        &self,
+        pipeline: &Pipeline,
+        force: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.path.as_ref().ok_or(
+            "Please pass the configuration file the pipeline is saved to through --config-file",
+        )?;
+        let content = std::fs::read_to_string(path)?;
+        let mut value = serde_json::from_str::<serde_json::Value>(&content)?;
+        let pipelines = value
+            .as_object_mut()
+            .ok_or("The configuration file must contain an object")?
+            .entry("pipelines")
+            .or_insert_with(|| serde_json::Value::Array(vec![]))
+            .as_array_mut()
+            .ok_or("'pipelines' must be an array")?;
+
+        let name = pipeline.name.as_str();
+        let mut entry = serde_json::to_value(pipeline)?;
+        entry["stages"] = pipeline
+            .stages
+            .iter()
+            .map(|stage| mongodb::bson::Bson::Document(stage.clone()).into_relaxed_extjson())
+            .collect();
+        match pipelines
+            .iter()
+            .position(|p| p.get("name").and_then(|n| n.as_str()) == Some(name))
+        {
+            Some(index) if force => pipelines[index] = entry,
+            Some(_) => {
+                return Err(format!(
+                    "There is already a pipeline named '{}'. Pass --force to replace it",
+                    name
+                )
+                .into())
+            }
+            None => pipelines.push(entry),
+        }
+
+        let indent = content
+            .lines()
+            .map(|line| &line[..line.len() - line.trim_start().len()])
+            .find(|indent| !indent.is_empty())
+            .unwrap_or("  ");
+        let mut output = vec![];
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+        let mut serializer = serde_json::Serializer::with_formatter(&mut output, formatter);
+        serde::Serialize::serialize(&value, &mut serializer)?;
+        if content.ends_with('\n') {
+            output.push(b'\n');
+        }
+        std::fs::write(path, output)?;
+        Ok(())
+    }
+
+    /// Resolve a path found in the configuration file relative to that file.
+    pub fn resolve_path(&self, path: &str) -> std::path::PathBuf {
+        match self.path.as_ref().and_then(|p| p.parent()) {
+            Some(directory) => directory.join(path),
+            None => path.into(),
+        }
+    }
+
+    /// The stages of a saved pipeline, read from its `stages_file` if it has one, with the
+    /// fragments it includes expanded.
+    pub fn pipeline_stages(
+        &self,
+        pipeline: &Pipeline,
+    ) -> Result<Vec<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+        let stages = match &pipeline.stages_file {
+            Some(_) if !pipeline.stages.is_empty() => {
+                return Err(format!(
+                    "The pipeline '{}' has both stages and a stages_file",
+                    pipeline.name
+                )
+                .into())
+            }
+            Some(stages_file) => read_pipeline_file(self.resolve_path(stages_file))?,
+            None => pipeline.stages.clone(),
+        };
+        self.expand_fragments(stages)
+    }
+
+    /// Replace every `{"$include": "<name>"}` stage with the stages of that fragment.
+    pub fn expand_fragments(
+        &self,
+        stages: Vec<mongodb::bson::Document>,
+    ) -> Result<Vec<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+        let mut expanded = vec![];
+        self.expand_fragments_into(stages, &mut vec![], &mut expanded)?;
+        Ok(expanded)
+    }
+
+    fn expand_fragments_into(
+        &self,
+        stages: Vec<mongodb::bson::Document>,
+        including: &mut Vec<String>,
+        expanded: &mut Vec<mongodb::bson::Document>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for stage in stages {
+            let name = match stage.get(INCLUDE) {
+                None => {
+                    expanded.push(stage);
+                    continue;
+                }
+                Some(mongodb::bson::Bson::String(name)) if stage.len() == 1 => name.clone(),
+                Some(_) => {
+                    return Err(format!(
+                        "Expected {{\"{}\": \"<fragment name>\"}} but got {}",
+                        INCLUDE, stage
+                    )
+                    .into())
+                }
+            };
+            if including.contains(&name) {
+                return Err(format!("The fragment '{}' includes itself", name).into());
+            }
+            let fragment = self
+                .fragments
+                .get(&name)
+                .ok_or_else(|| format!("There are no fragment named '{}'", name))?;
+            including.push(name);
+            self.expand_fragments_into(fragment.clone(), including, expanded)?;
+            including.pop();
+        }
+        Ok(())
+    }
+
+    pub fn pipeline_by_index(&self, index: usize) -> Result<&Pipeline, Box<dyn std::error::Error>> {
+        let pipeline_count = self.pipelines.len();
+        self.pipelines.get(index).ok_or_else(|| {
+            format!(
+                "There are only {} pipeline{} available. \
+            Note that it is 0-indexed",
+                pipeline_count,
+                if pipeline_count == 1 { "" } else { "s" },
+            )
+            .into()
+        })
+    }
+
+    pub fn pipeline_by_name(&self, name: &str) -> Result<&Pipeline, Box<dyn std::error::Error>> {
+        self.pipelines
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| format!("There are no pipeline named '{}'", name).into())
+    }
+
+    pub fn query_by_name(&self, name: &str) -> Result<&Query, Box<dyn std::error::Error>> {
+        self.queries
+            .iter()
+            .find(|q| q.name == name)
+            .ok_or_else(|| format!("There are no query named '{}'", name).into())
+    }
 }
 
-#[derive(Debug)]
-pub enum InsertResult {
-    One(mongodb::results::InsertOneResult),
-    Many(mongodb::results::InsertManyResult),
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// The driver's own representation of a document, with ObjectIds and dates stringified.
+    #[default]
+    Document,
+    /// Relaxed Extended JSON, one document per line.
+    Json,
+    /// Relaxed Extended JSON, indented.
+    PrettyJson,
+}
+
+impl OutputFormat {
+    pub fn format_document(self, document: &mongodb::bson::Document) -> String {
+        match self {
+            OutputFormat::Document => stringify_document(document).to_string(),
+            OutputFormat::Json => mongodb::bson::Bson::Document(document.clone())
+                .into_relaxed_extjson()
+                .to_string(),
+            OutputFormat::PrettyJson => serde_json::to_string_pretty(
+                &mongodb::bson::Bson::Document(document.clone()).into_relaxed_extjson(),
+            )
+            .unwrap_or_default(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -103,27 +460,369 @@ pub enum InputType {
     BufReader(std::io::BufReader<std::fs::File>),
 }
 
-pub fn find_one_args() -> Vec<clap::Arg<'static, 'static>> {
-    vec![
-        clap::Arg::with_name(keywords::INPUT_FILTER)
-            .long(keywords::INPUT_FILTER)
-            .help("The filter to be applied")
-            .takes_value(true)
-            .required(false),
-        clap::Arg::with_name(keywords::PROJECT)
-            .long(keywords::PROJECT)
-            .help("Project the resulting documents")
-            .takes_value(true)
-            .required(false),
-    ]
+impl InputType {
+    /// Pick the input source in order of precedence: `--input-file`, the argument named
+    /// `arg_name` and finally stdin if something is being piped in.
+    pub fn from_args(
+        input_file: Option<&str>,
+        arg: Option<&str>,
+        arg_name: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(file) = input_file {
+            Ok(InputType::BufReader(std::io::BufReader::new(
+                std::fs::File::open(file)?,
+            )))
+        } else if let Some(arg) = arg {
+            Ok(InputType::Arg(arg.to_string()))
+        } else if !atty::is(atty::Stream::Stdin) {
+            Ok(InputType::Stdin(std::io::stdin()))
+        } else {
+            Err(format!(
+                "Please provide an input either by piping something in, \
+                passing it through '--{}' or specifying a file with '--input-file <file>'",
+                arg_name
+            )
+            .into())
+        }
+    }
+
+    pub fn into_reader(self) -> Box<dyn std::io::BufRead> {
+        match self {
+            InputType::Stdin(s) => Box::new(s.lock()),
+            InputType::Arg(s) => Box::new(std::io::Cursor::new(s.into_bytes())),
+            InputType::BufReader(b) => Box::new(b),
+        }
+    }
+}
+
+/// The pseudo-stage including a fragment of the configuration file.
+const INCLUDE: &str = "$include";
+
+thread_local! {
+    static CLIENTS: RefCell<HashMap<String, mongodb::sync::Client>> = RefCell::new(HashMap::new());
+}
+
+/// Passes the command events on to each of the handlers.
+struct CommandEventHandlers(Vec<std::sync::Arc<dyn CommandEventHandler>>);
+
+impl CommandEventHandler for CommandEventHandlers {
+    fn handle_command_started_event(&self, event: CommandStartedEvent) {
+        for handler in &self.0 {
+            handler.handle_command_started_event(event.clone());
+        }
+    }
+
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        for handler in &self.0 {
+            handler.handle_command_succeeded_event(event.clone());
+        }
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        for handler in &self.0 {
+            handler.handle_command_failed_event(event.clone());
+        }
+    }
+}
+
+/// Connect to the deployment at `connection_uri`, reusing the client of an earlier call with
+/// the same URI. This keeps a single connection pool open for the lifetime of the shell.
+pub fn connect(connection_uri: &str) -> mongodb::error::Result<mongodb::sync::Client> {
+    CLIENTS.with(|clients| {
+        if let Some(client) = clients.borrow().get(connection_uri) {
+            return Ok(client.clone());
+        }
+        let mut options = mongodb::options::ClientOptions::parse(connection_uri)?;
+        let mut handlers: Vec<std::sync::Arc<dyn CommandEventHandler>> = vec![];
+        if logging::enabled(logging::Level::Info) {
+            logging::log(
+                logging::Level::Info,
+                "connecting",
+                vec![
+                    ("uri", logging::redact_uri(connection_uri).into()),
+                    (
+                        "hosts",
+                        options
+                            .hosts
+                            .iter()
+                            .map(|host| host.to_string())
+                            .collect::<Vec<_>>()
+                            .into(),
+                    ),
+                    ("replica_set", serde_json::json!(options.repl_set_name)),
+                    (
+                        "direct_connection",
+                        serde_json::json!(options.direct_connection),
+                    ),
+                    ("tls", options.tls.is_some().into()),
+                ],
+            );
+            handlers.push(std::sync::Arc::new(logging::CommandLogger::default()));
+        }
+        if stats::enabled() {
+            stats::record_connecting();
+            handlers.push(std::sync::Arc::new(stats::StatsRecorder));
+        }
+        options.command_event_handler = match handlers.len() {
+            0 | 1 => handlers.pop(),
+            _ => Some(std::sync::Arc::new(CommandEventHandlers(handlers))),
+        };
+        let client = mongodb::sync::Client::with_options(options)?;
+        clients
+            .borrow_mut()
+            .insert(connection_uri.to_string(), client.clone());
+        Ok(client)
+    })
+}
+
+/// The filter of the subcommands reading or deleting documents.
+#[derive(clap::Args, Debug)]
+pub struct FilterArgs {
+    /// The filter to be applied
+    #[arg(long, value_parser = parse_document)]
+    pub input_filter: Option<mongodb::bson::Document>,
+}
+
+/// The filter and projection of find-one and find-many.
+#[derive(clap::Args, Debug)]
+pub struct FindArgs {
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    /// Project the resulting documents
+    #[arg(long, value_parser = parse_document)]
+    pub project: Option<mongodb::bson::Document>,
+}
+
+pub fn read_pipeline_file<P: AsRef<std::path::Path>>(
+    path: P,
+) -> Result<Vec<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path.as_ref())
+        .map_err(|e| format!("Cannot read {}: {}", path.as_ref().display(), e))?;
+    parse_pipeline(&content)
+}
+
+/// Parse an aggregation pipeline given as a JSON array of stages.
+/// Stages are read as Extended JSON.
+pub fn parse_pipeline(
+    pipeline_str: &str,
+) -> Result<Vec<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+    match serde_json::from_str::<serde_json::Value>(pipeline_str)? {
+        serde_json::Value::Array(pipeline) => pipeline
+            .into_iter()
+            .map(|s| match s {
+                serde_json::Value::Object(o) => Ok(mongodb::bson::document::Document::try_from(o)?),
+                _ => Err("Each stage must be a valid object".into()),
+            })
+            .collect(),
+        _ => Err("Aggregation pipeline must be an array".into()),
+    }
+}
+
+/// Parse every non-empty line of the reader as `T`.
+/// Each value is paired with its 1-indexed line number so that errors can point back to it.
+pub fn read_json_lines<T, R>(reader: R) -> Result<Vec<(usize, T)>, Box<dyn std::error::Error>>
+where
+    T: serde::de::DeserializeOwned,
+    R: std::io::BufRead,
+{
+    let mut vec = Vec::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value =
+            serde_json::from_str::<T>(&line).map_err(|e| format!("Line {}: {}", idx + 1, e))?;
+        vec.push((idx + 1, value));
+    }
+    Ok(vec)
+}
+
+pub fn parse_read_concern(level: &str) -> mongodb::options::ReadConcern {
+    mongodb::options::ReadConcern::custom(level.to_string())
+}
+
+/// Accepts either a number of nodes, "majority" or the name of a custom write concern.
+pub fn parse_write_concern(w: &str) -> mongodb::options::WriteConcern {
+    let acknowledgment = match w.parse::<u32>() {
+        Ok(nodes) => mongodb::options::Acknowledgment::from(nodes),
+        Err(_) => mongodb::options::Acknowledgment::from(w.to_string()),
+    };
+    mongodb::options::WriteConcern::builder()
+        .w(acknowledgment)
+        .build()
+}
+
+/// Returns the first batch of a cursor-returning command such as `listIndexes`.
+pub fn first_batch(
+    reply: &mongodb::bson::Document,
+) -> Result<Vec<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+    Ok(reply
+        .get_document("cursor")?
+        .get_array("firstBatch")?
+        .iter()
+        .filter_map(|b| b.as_document().cloned())
+        .collect())
+}
+
+pub fn list_index_specs(
+    database: &mongodb::sync::Database,
+    collection_name: &str,
+) -> Result<Vec<mongodb::bson::Document>, Box<dyn std::error::Error>> {
+    first_batch(
+        &database.run_command(mongodb::bson::doc! { "listIndexes": collection_name }, None)?,
+    )
+}
+
+/// Create the given indexes, as returned by `listIndexes`, on another collection.
+/// The default `_id` index is skipped. Returns the number of indexes created.
+pub fn create_index_specs(
+    database: &mongodb::sync::Database,
+    collection_name: &str,
+    specs: &[mongodb::bson::Document],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let indexes = specs
+        .iter()
+        .filter(|i| i.get_str("name") != Ok("_id_"))
+        .map(|i| {
+            let mut index = i.clone();
+            index.remove("ns");
+            mongodb::bson::Bson::Document(index)
+        })
+        .collect::<Vec<_>>();
+    if !indexes.is_empty() {
+        database.run_command(
+            mongodb::bson::doc! {
+                "createIndexes": collection_name,
+                "indexes": &indexes,
+            },
+            None,
+        )?;
+    }
+    Ok(indexes.len())
+}
+
+/// Ask a yes or no question on stdin. Anything but "y" or "yes" is a no.
+pub fn confirm(
+    question: &str,
+    out: &mut dyn std::io::Write,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    write!(out, "{} [y/N] ", question)?;
+    out.flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Match `value` against `pattern` where '*' stands for any sequence of characters.
+/// Returns what each '*' matched, in order.
+pub fn match_wildcards(pattern: &str, value: &str) -> Option<Vec<String>> {
+    let parts = pattern.split('*').collect::<Vec<_>>();
+    let mut rest = value.strip_prefix(parts[0])?;
+    let mut captures = Vec::new();
+    if parts.len() == 1 {
+        return if rest.is_empty() {
+            Some(captures)
+        } else {
+            None
+        };
+    }
+    for (idx, part) in parts.iter().enumerate().skip(1) {
+        if idx == parts.len() - 1 {
+            let capture = rest.strip_suffix(part)?;
+            captures.push(capture.to_string());
+        } else {
+            let position = rest.find(part)?;
+            captures.push(rest[..position].to_string());
+            rest = &rest[position + part.len()..];
+        }
+    }
+    Some(captures)
+}
+
+/// A number of bytes in the largest unit it is at least one of, e.g. "1.5 KiB".
+pub fn format_size(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024.0 {
+        return format!("{} B", bytes.round());
+    }
+    let mut size = bytes / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// The value at a dotted path through embedded documents, e.g. 'meta.id'.
+pub fn get_path<'a>(
+    document: &'a mongodb::bson::Document,
+    path: &str,
+) -> Option<&'a mongodb::bson::Bson> {
+    let mut keys = path.split('.');
+    let mut value = document.get(keys.next()?)?;
+    for key in keys {
+        value = value.as_document()?.get(key)?;
+    }
+    Some(value)
+}
+
+/// The number in the field, whatever its numeric type.
+pub fn get_number(document: &mongodb::bson::Document, key: &str) -> Option<f64> {
+    document.get(key).and_then(crate::validation::as_number)
+}
+
+/// Print the fields as a table of names and values.
+pub fn print_fields(
+    fields: Vec<(&str, String)>,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut table = prettytable::Table::new();
+    for (name, value) in fields {
+        table.add_row(prettytable::Row::new(vec![
+            prettytable::Cell::new(name),
+            prettytable::Cell::new(&value),
+        ]));
+    }
+    table.print(out)?;
+    Ok(())
+}
+
+/// The alias of the BSON type as used by `$type` and `$jsonSchema`'s `bsonType`.
+pub fn bson_type_name(bson: &mongodb::bson::Bson) -> &'static str {
+    match bson {
+        mongodb::bson::Bson::Double(_) => "double",
+        mongodb::bson::Bson::String(_) => "string",
+        mongodb::bson::Bson::Array(_) => "array",
+        mongodb::bson::Bson::Document(_) => "object",
+        mongodb::bson::Bson::Boolean(_) => "bool",
+        mongodb::bson::Bson::Null => "null",
+        mongodb::bson::Bson::RegularExpression(_) => "regex",
+        mongodb::bson::Bson::JavaScriptCode(_) => "javascript",
+        mongodb::bson::Bson::JavaScriptCodeWithScope(_) => "javascriptWithScope",
+        mongodb::bson::Bson::Int32(_) => "int",
+        mongodb::bson::Bson::Int64(_) => "long",
+        mongodb::bson::Bson::Timestamp(_) => "timestamp",
+        mongodb::bson::Bson::Binary(_) => "binData",
+        mongodb::bson::Bson::ObjectId(_) => "objectId",
+        mongodb::bson::Bson::DateTime(_) => "date",
+        mongodb::bson::Bson::Symbol(_) => "symbol",
+        mongodb::bson::Bson::Decimal128(_) => "decimal",
+        mongodb::bson::Bson::Undefined => "undefined",
+        mongodb::bson::Bson::MaxKey => "maxKey",
+        mongodb::bson::Bson::MinKey => "minKey",
+        mongodb::bson::Bson::DbPointer(_) => "dbPointer",
+    }
 }
 
-pub fn delete_args() -> Vec<clap::Arg<'static, 'static>> {
-    vec![clap::Arg::with_name(keywords::INPUT_FILTER)
-        .long(keywords::INPUT_FILTER)
-        .help("The filter to be applied")
-        .takes_value(true)
-        .required(false)]
+pub fn bson_as_i64(bson: &mongodb::bson::Bson) -> Option<i64> {
+    match bson {
+        mongodb::bson::Bson::Int32(i) => Some(*i as i64),
+        mongodb::bson::Bson::Int64(i) => Some(*i),
+        mongodb::bson::Bson::Double(d) => Some(*d as i64),
+        _ => None,
+    }
 }
 
 pub fn stringify_document(
@@ -176,6 +875,36 @@ pub fn convert_json_value_to_bson_document(
     }
 }
 
+/// Parse an argument given as an Extended JSON object, like pipelines, so that filters can
+/// match e.g. `{"$oid": ...}` ids and integers. Used as the value parser of the arguments.
+pub fn parse_document(s: &str) -> Result<mongodb::bson::Document, String> {
+    match serde_json::from_str::<serde_json::Value>(s).map_err(|e| e.to_string())? {
+        serde_json::Value::Object(o) => {
+            mongodb::bson::Document::try_from(o).map_err(|e| e.to_string())
+        }
+        _ => Err("must be an object".to_string()),
+    }
+}
+
+/// The stages of a pipeline given as a single argument. An alias, so that clap does not take
+/// the `Vec` for an argument that may be repeated.
+pub type Stages = Vec<mongodb::bson::Document>;
+
+/// Like `parse_pipeline`, as the value parser of the arguments.
+pub fn parse_pipeline_arg(s: &str) -> Result<Stages, String> {
+    parse_pipeline(s).map_err(|e| e.to_string())
+}
+
+/// Like `convert_json_value_to_bson_document` but names the offending field when the value
+/// is not an object.
+pub fn json_to_bson_document(
+    value: &serde_json::Value,
+    field: &str,
+) -> Result<mongodb::bson::Document, Box<dyn std::error::Error>> {
+    convert_json_value_to_bson_document(value)
+        .ok_or_else(|| format!("'{}' must be an object", field).into())
+}
+
 pub fn convert_json_to_bson(json: &serde_json::Value) -> mongodb::bson::Bson {
     match json {
         serde_json::Value::Null => mongodb::bson::Bson::Null,
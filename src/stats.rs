@@ -0,0 +1,185 @@
+//! The summary printed to stderr with `--stats` once the subcommand finishes, gathered from the
+//! commands the driver sends to the server.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use mongodb::event::command::{
+    CommandEventHandler, CommandFailedEvent, CommandStartedEvent, CommandSucceededEvent,
+};
+
+#[derive(Debug, Default)]
+struct Stats {
+    /// When the first client was created.
+    connecting: Option<Instant>,
+    /// When the first command was sent, once a connection was established.
+    first_command: Option<Instant>,
+    /// The time the commands took, from the driver's point of view.
+    server: Duration,
+    commands: u64,
+    get_mores: u64,
+    returned: u64,
+    affected: u64,
+    bytes_received: u64,
+}
+
+impl Stats {
+    fn record_reply(&mut self, command_name: &str, reply: &mongodb::bson::Document) {
+        self.bytes_received += mongodb::bson::to_vec(reply)
+            .map(|bytes| bytes.len() as u64)
+            .unwrap_or(0);
+        match command_name {
+            "insert" | "update" | "delete" => self.affected += count(reply.get("n")),
+            "findAndModify" => {
+                self.affected += reply
+                    .get_document("lastErrorObject")
+                    .map(|last_error| count(last_error.get("n")))
+                    .unwrap_or(0)
+            }
+            _ => {
+                if let Ok(cursor) = reply.get_document("cursor") {
+                    self.returned += cursor
+                        .get_array("firstBatch")
+                        .or_else(|_| cursor.get_array("nextBatch"))
+                        .map(|batch| batch.len() as u64)
+                        .unwrap_or(0);
+                }
+            }
+        }
+    }
+}
+
+fn count(value: Option<&mongodb::bson::Bson>) -> u64 {
+    match value {
+        Some(mongodb::bson::Bson::Int32(n)) => *n as u64,
+        Some(mongodb::bson::Bson::Int64(n)) => *n as u64,
+        Some(mongodb::bson::Bson::Double(n)) => *n as u64,
+        _ => 0,
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static STATS: Mutex<Option<Stats>> = Mutex::new(None);
+
+/// Start gathering the statistics. The wall time is measured from the first call.
+pub fn enable() {
+    STARTED.get_or_init(Instant::now);
+    if let Ok(mut stats) = STATS.lock() {
+        stats.get_or_insert_with(Stats::default);
+    }
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn with_stats(f: impl FnOnce(&mut Stats)) {
+    if let Ok(mut stats) = STATS.lock() {
+        if let Some(stats) = stats.as_mut() {
+            f(stats);
+        }
+    }
+}
+
+/// Note that a client is being created. The time until its first command is the connect phase.
+pub fn record_connecting() {
+    with_stats(|stats| {
+        stats.connecting.get_or_insert_with(Instant::now);
+    });
+}
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Write the summary, if the statistics were enabled.
+pub fn print_summary(out: &mut dyn std::io::Write) -> std::io::Result<()> {
+    let started = match STARTED.get() {
+        Some(started) if enabled() => *started,
+        _ => return Ok(()),
+    };
+    let wall = started.elapsed();
+    let stats = STATS.lock().ok();
+    let default = Stats::default();
+    let stats = stats
+        .as_ref()
+        .and_then(|stats| stats.as_ref())
+        .unwrap_or(&default);
+    // Without any command sent, the time after creating the client was spent failing to connect.
+    let connect = match stats.connecting {
+        Some(connecting) => stats
+            .first_command
+            .unwrap_or_else(Instant::now)
+            .saturating_duration_since(connecting),
+        None => Duration::ZERO,
+    };
+    let output = wall.saturating_sub(connect).saturating_sub(stats.server);
+    writeln!(out, "Wall time: {}", milliseconds(wall))?;
+    writeln!(out, "  Connect: {}", milliseconds(connect))?;
+    writeln!(out, "  Server: {}", milliseconds(stats.server))?;
+    writeln!(out, "  Output: {}", milliseconds(output))?;
+    writeln!(out, "Documents returned: {}", stats.returned)?;
+    writeln!(out, "Documents affected: {}", stats.affected)?;
+    writeln!(out, "Bytes received: {}", stats.bytes_received)?;
+    writeln!(out, "Commands: {}", stats.commands)?;
+    writeln!(out, "getMore round trips: {}", stats.get_mores)?;
+    Ok(())
+}
+
+/// Records the commands the driver sends to the server.
+#[derive(Debug, Default)]
+pub struct StatsRecorder;
+
+impl CommandEventHandler for StatsRecorder {
+    fn handle_command_started_event(&self, event: CommandStartedEvent) {
+        with_stats(|stats| {
+            stats.first_command.get_or_insert_with(Instant::now);
+            stats.commands += 1;
+            if event.command_name == "getMore" {
+                stats.get_mores += 1;
+            }
+        });
+    }
+
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        with_stats(|stats| {
+            stats.server += event.duration;
+            stats.record_reply(&event.command_name, &event.reply);
+        });
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        with_stats(|stats| stats.server += event.duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    #[test]
+    fn counts_the_documents_of_the_replies() {
+        let mut stats = Stats::default();
+        stats.record_reply(
+            "find",
+            &doc! { "cursor": { "firstBatch": [{ "_id": 1 }, { "_id": 2 }], "id": 7_i64 }, "ok": 1.0 },
+        );
+        stats.record_reply(
+            "getMore",
+            &doc! { "cursor": { "nextBatch": [{ "_id": 3 }], "id": 0_i64 }, "ok": 1.0 },
+        );
+        stats.record_reply("update", &doc! { "n": 2, "nModified": 1, "ok": 1.0 });
+        stats.record_reply(
+            "findAndModify",
+            &doc! { "lastErrorObject": { "n": 1 }, "value": null, "ok": 1.0 },
+        );
+        stats.record_reply("ping", &doc! { "ok": 1.0 });
+        assert_eq!(stats.returned, 3);
+        assert_eq!(stats.affected, 3);
+        assert!(stats.bytes_received > 0);
+    }
+}
@@ -0,0 +1,763 @@
+//! Local evaluation of MongoDB's `$jsonSchema` validators.
+//!
+//! Only the keywords supported by the server are evaluated. Unlike the server, every violation
+//! is collected alongside the path of the offending field so that it can be reported.
+
+use std::convert::TryFrom;
+
+use crate::shared::{bson_as_i64, bson_type_name};
+
+#[derive(Debug)]
+pub struct Violation {
+    /// Dotted path of the offending field, empty for the document itself.
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "(document): {}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Read a schema from a file containing either a `{"$jsonSchema": {...}}` validator or the schema
+/// itself.
+pub fn read_json_schema(path: &str) -> Result<mongodb::bson::Document, Box<dyn std::error::Error>> {
+    let schema = match serde_json::from_reader::<_, serde_json::Value>(std::fs::File::open(path)?)?
+    {
+        serde_json::Value::Object(o) => mongodb::bson::Document::try_from(o)?,
+        _ => return Err("The schema must be an object".into()),
+    };
+    match schema.get_document("$jsonSchema") {
+        Ok(inner) => Ok(inner.clone()),
+        Err(_) => Ok(schema),
+    }
+}
+
+/// Validate a document against the schema given to `$jsonSchema`.
+pub fn validate_document(
+    schema: &mongodb::bson::Document,
+    document: &mongodb::bson::Document,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    validate(
+        schema,
+        &mongodb::bson::Bson::Document(document.clone()),
+        "",
+        &mut violations,
+    );
+    violations
+}
+
+/// The closest double to a Decimal128, decoded from its IEEE 754-2008 binary integer decimal
+/// bytes since bson offers no conversion.
+fn decimal128_to_f64(decimal: &mongodb::bson::Decimal128) -> f64 {
+    let bits = u128::from_le_bytes(decimal.bytes());
+    let sign = if bits >> 127 == 1 { -1.0 } else { 1.0 };
+    match (bits >> 122) & 0x1f {
+        0x1f => return f64::NAN,
+        0x1e => return sign * f64::INFINITY,
+        _ => {}
+    }
+    let (exponent, significand) = if (bits >> 125) & 0b11 == 0b11 {
+        // The significand would exceed the 34 digits of a canonical value, which means zero.
+        ((bits >> 111) & 0x3fff, 0)
+    } else {
+        ((bits >> 113) & 0x3fff, bits & ((1 << 113) - 1))
+    };
+    let significand = if significand >= 10_u128.pow(34) {
+        0
+    } else {
+        significand
+    };
+    // Parsing the decimal notation rounds to the nearest double.
+    let magnitude = format!("{}e{}", significand, exponent as i64 - 6176)
+        .parse::<f64>()
+        .unwrap_or_default();
+    sign * magnitude
+}
+
+pub(crate) fn as_number(bson: &mongodb::bson::Bson) -> Option<f64> {
+    match bson {
+        mongodb::bson::Bson::Double(d) => Some(*d),
+        mongodb::bson::Bson::Decimal128(d) => Some(decimal128_to_f64(d)),
+        o => bson_as_i64(o).map(|i| i as f64),
+    }
+}
+
+pub(crate) fn is_number(bson: &mongodb::bson::Bson) -> bool {
+    matches!(
+        bson,
+        mongodb::bson::Bson::Double(_)
+            | mongodb::bson::Bson::Int32(_)
+            | mongodb::bson::Bson::Int64(_)
+            | mongodb::bson::Bson::Decimal128(_)
+    )
+}
+
+fn matches_bson_type(value: &mongodb::bson::Bson, bson_type: &str) -> bool {
+    match bson_type {
+        "number" => is_number(value),
+        o => bson_type_name(value) == o,
+    }
+}
+
+fn matches_json_type(value: &mongodb::bson::Bson, json_type: &str) -> bool {
+    match (json_type, value) {
+        ("object", mongodb::bson::Bson::Document(_)) => true,
+        ("array", mongodb::bson::Bson::Array(_)) => true,
+        ("boolean", mongodb::bson::Bson::Boolean(_)) => true,
+        ("string", mongodb::bson::Bson::String(_)) => true,
+        ("null", mongodb::bson::Bson::Null) => true,
+        ("number", o) => is_number(o),
+        _ => false,
+    }
+}
+
+/// Equality where numbers compare by value regardless of their BSON type.
+pub(crate) fn bson_equal(a: &mongodb::bson::Bson, b: &mongodb::bson::Bson) -> bool {
+    match (a, b) {
+        (a, b) if is_number(a) && is_number(b) => {
+            matches!((as_number(a), as_number(b)), (Some(a), Some(b)) if a == b)
+        }
+        (mongodb::bson::Bson::Array(a), mongodb::bson::Bson::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| bson_equal(a, b))
+        }
+        (mongodb::bson::Bson::Document(a), mongodb::bson::Bson::Document(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|((ka, va), (kb, vb))| ka == kb && bson_equal(va, vb))
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// The accepted type names of `bsonType` or `type`, which take either a string or an array.
+fn type_names(bson: &mongodb::bson::Bson) -> Vec<&str> {
+    match bson {
+        mongodb::bson::Bson::String(s) => vec![s.as_str()],
+        mongodb::bson::Bson::Array(a) => a.iter().filter_map(|b| b.as_str()).collect(),
+        _ => vec![],
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn is_valid(schema: &mongodb::bson::Document, value: &mongodb::bson::Bson, path: &str) -> bool {
+    let mut violations = Vec::new();
+    validate(schema, value, path, &mut violations);
+    violations.is_empty()
+}
+
+fn validate(
+    schema: &mongodb::bson::Document,
+    value: &mongodb::bson::Bson,
+    path: &str,
+    violations: &mut Vec<Violation>,
+) {
+    let mut violation = |message: String| {
+        violations.push(Violation {
+            path: path.to_string(),
+            message,
+        })
+    };
+
+    if let Some(bson_type) = schema.get("bsonType") {
+        let names = type_names(bson_type);
+        if !names.iter().any(|t| matches_bson_type(value, t)) {
+            violation(format!(
+                "expected bsonType {} but found {}",
+                names.join(" or "),
+                bson_type_name(value)
+            ));
+        }
+    }
+    if let Some(json_type) = schema.get("type") {
+        let names = type_names(json_type);
+        if !names.iter().any(|t| matches_json_type(value, t)) {
+            violation(format!(
+                "expected type {} but found {}",
+                names.join(" or "),
+                bson_type_name(value)
+            ));
+        }
+    }
+    if let Ok(allowed) = schema.get_array("enum") {
+        if !allowed.iter().any(|a| bson_equal(a, value)) {
+            violation(format!(
+                "{} is not one of the allowed values {}",
+                value,
+                mongodb::bson::Bson::Array(allowed.clone())
+            ));
+        }
+    }
+
+    if let Some(number) = as_number(value).filter(|_| is_number(value)) {
+        if let Some(minimum) = schema.get("minimum").and_then(as_number) {
+            if schema.get_bool("exclusiveMinimum").unwrap_or(false) {
+                if number <= minimum {
+                    violation(format!("{} is not greater than {}", number, minimum));
+                }
+            } else if number < minimum {
+                violation(format!("{} is less than the minimum {}", number, minimum));
+            }
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(as_number) {
+            if schema.get_bool("exclusiveMaximum").unwrap_or(false) {
+                if number >= maximum {
+                    violation(format!("{} is not less than {}", number, maximum));
+                }
+            } else if number > maximum {
+                violation(format!(
+                    "{} is greater than the maximum {}",
+                    number, maximum
+                ));
+            }
+        }
+        if let Some(multiple_of) = schema.get("multipleOf").and_then(as_number) {
+            if multiple_of != 0.0 && (number / multiple_of).fract() != 0.0 {
+                violation(format!("{} is not a multiple of {}", number, multiple_of));
+            }
+        }
+    }
+
+    if let mongodb::bson::Bson::String(s) = value {
+        let length = s.chars().count() as i64;
+        if let Some(min_length) = schema.get("minLength").and_then(bson_as_i64) {
+            if length < min_length {
+                violation(format!(
+                    "length {} is shorter than the minimum {}",
+                    length, min_length
+                ));
+            }
+        }
+        if let Some(max_length) = schema.get("maxLength").and_then(bson_as_i64) {
+            if length > max_length {
+                violation(format!(
+                    "length {} is longer than the maximum {}",
+                    length, max_length
+                ));
+            }
+        }
+        if let Ok(pattern) = schema.get_str("pattern") {
+            match regex::Regex::new(pattern) {
+                Ok(re) if !re.is_match(s) => {
+                    violation(format!("'{}' does not match the pattern '{}'", s, pattern))
+                }
+                Ok(_) => {}
+                Err(e) => violation(format!("invalid pattern '{}': {}", pattern, e)),
+            }
+        }
+    }
+
+    if let mongodb::bson::Bson::Array(array) = value {
+        let length = array.len() as i64;
+        if let Some(min_items) = schema.get("minItems").and_then(bson_as_i64) {
+            if length < min_items {
+                violation(format!(
+                    "{} item{} is fewer than the minimum {}",
+                    length,
+                    if length == 1 { "" } else { "s" },
+                    min_items
+                ));
+            }
+        }
+        if let Some(max_items) = schema.get("maxItems").and_then(bson_as_i64) {
+            if length > max_items {
+                violation(format!(
+                    "{} item{} is more than the maximum {}",
+                    length,
+                    if length == 1 { "" } else { "s" },
+                    max_items
+                ));
+            }
+        }
+        if schema.get_bool("uniqueItems").unwrap_or(false) {
+            let has_duplicates = array
+                .iter()
+                .enumerate()
+                .any(|(idx, a)| array[idx + 1..].iter().any(|b| bson_equal(a, b)));
+            if has_duplicates {
+                violation("items are not unique".to_string());
+            }
+        }
+    }
+
+    let mut required_missing = Vec::new();
+    if let mongodb::bson::Bson::Document(document) = value {
+        let length = document.len() as i64;
+        if let Some(min_properties) = schema.get("minProperties").and_then(bson_as_i64) {
+            if length < min_properties {
+                violation(format!(
+                    "{} field{} is fewer than the minimum {}",
+                    length,
+                    if length == 1 { "" } else { "s" },
+                    min_properties
+                ));
+            }
+        }
+        if let Some(max_properties) = schema.get("maxProperties").and_then(bson_as_i64) {
+            if length > max_properties {
+                violation(format!(
+                    "{} field{} is more than the maximum {}",
+                    length,
+                    if length == 1 { "" } else { "s" },
+                    max_properties
+                ));
+            }
+        }
+        if let Ok(required) = schema.get_array("required") {
+            required_missing = required
+                .iter()
+                .filter_map(|r| r.as_str())
+                .filter(|r| !document.contains_key(r))
+                .map(|r| r.to_string())
+                .collect();
+        }
+    }
+    for missing in required_missing {
+        violations.push(Violation {
+            path: join_path(path, &missing),
+            message: "required field is missing".to_string(),
+        });
+    }
+
+    match value {
+        mongodb::bson::Bson::Document(document) => {
+            validate_properties(schema, document, path, violations)
+        }
+        mongodb::bson::Bson::Array(array) => validate_items(schema, array, path, violations),
+        _ => {}
+    }
+
+    if let Ok(all_of) = schema.get_array("allOf") {
+        for subschema in all_of.iter().filter_map(|s| s.as_document()) {
+            validate(subschema, value, path, violations);
+        }
+    }
+    if let Ok(any_of) = schema.get_array("anyOf") {
+        let subschemas = any_of.iter().filter_map(|s| s.as_document());
+        if !subschemas.clone().any(|s| is_valid(s, value, path)) {
+            violations.push(Violation {
+                path: path.to_string(),
+                message: "does not match any of the schemas in anyOf".to_string(),
+            });
+        }
+    }
+    if let Ok(one_of) = schema.get_array("oneOf") {
+        let matching = one_of
+            .iter()
+            .filter_map(|s| s.as_document())
+            .filter(|s| is_valid(s, value, path))
+            .count();
+        if matching != 1 {
+            violations.push(Violation {
+                path: path.to_string(),
+                message: format!(
+                    "matches {} of the schemas in oneOf instead of exactly one",
+                    matching
+                ),
+            });
+        }
+    }
+    if let Ok(not) = schema.get_document("not") {
+        if is_valid(not, value, path) {
+            violations.push(Violation {
+                path: path.to_string(),
+                message: "matches the schema in not".to_string(),
+            });
+        }
+    }
+}
+
+fn validate_properties(
+    schema: &mongodb::bson::Document,
+    document: &mongodb::bson::Document,
+    path: &str,
+    violations: &mut Vec<Violation>,
+) {
+    let properties = schema.get_document("properties").ok();
+    let pattern_properties = schema
+        .get_document("patternProperties")
+        .map(|p| {
+            p.iter()
+                .filter_map(|(pattern, s)| {
+                    Some((regex::Regex::new(pattern).ok()?, s.as_document()?))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    for (key, field) in document {
+        let field_path = join_path(path, key);
+        let mut described = false;
+        if let Some(field_schema) = properties.and_then(|p| p.get_document(key).ok()) {
+            described = true;
+            validate(field_schema, field, &field_path, violations);
+        }
+        for (_, field_schema) in pattern_properties.iter().filter(|(re, _)| re.is_match(key)) {
+            described = true;
+            validate(field_schema, field, &field_path, violations);
+        }
+        if described {
+            continue;
+        }
+        match schema.get("additionalProperties") {
+            Some(mongodb::bson::Bson::Boolean(false)) => violations.push(Violation {
+                path: field_path,
+                message: "additional field is not allowed".to_string(),
+            }),
+            Some(mongodb::bson::Bson::Document(additional)) => {
+                validate(additional, field, &field_path, violations)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn validate_items(
+    schema: &mongodb::bson::Document,
+    array: &[mongodb::bson::Bson],
+    path: &str,
+    violations: &mut Vec<Violation>,
+) {
+    match schema.get("items") {
+        Some(mongodb::bson::Bson::Document(items)) => {
+            for (idx, item) in array.iter().enumerate() {
+                validate(items, item, &join_path(path, &idx.to_string()), violations);
+            }
+        }
+        Some(mongodb::bson::Bson::Array(items)) => {
+            for (idx, item) in array.iter().enumerate() {
+                let item_path = join_path(path, &idx.to_string());
+                match items.get(idx) {
+                    Some(mongodb::bson::Bson::Document(item_schema)) => {
+                        validate(item_schema, item, &item_path, violations)
+                    }
+                    Some(_) => {}
+                    None => match schema.get("additionalItems") {
+                        Some(mongodb::bson::Bson::Boolean(false)) => violations.push(Violation {
+                            path: item_path,
+                            message: "additional item is not allowed".to_string(),
+                        }),
+                        Some(mongodb::bson::Bson::Document(additional)) => {
+                            validate(additional, item, &item_path, violations)
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    /// The Decimal128 `significand * 10^exponent`.
+    fn decimal(significand: i64, exponent: i32) -> mongodb::bson::Bson {
+        let mut bits = (((exponent + 6176) as u128) << 113) | significand.unsigned_abs() as u128;
+        if significand < 0 {
+            bits |= 1 << 127;
+        }
+        mongodb::bson::Bson::Decimal128(mongodb::bson::Decimal128::from_bytes(bits.to_le_bytes()))
+    }
+
+    fn violations(
+        schema: mongodb::bson::Document,
+        document: mongodb::bson::Document,
+    ) -> Vec<String> {
+        validate_document(&schema, &document)
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn checks_the_bson_type() {
+        let schema = doc! { "properties": { "a": { "bsonType": "int" } } };
+        assert!(violations(schema.clone(), doc! { "a": 1 }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": 1_i64 }),
+            ["a: expected bsonType int but found long"]
+        );
+
+        let schema = doc! { "properties": { "a": { "bsonType": ["long", "double"] } } };
+        assert!(violations(schema.clone(), doc! { "a": 1_i64 }).is_empty());
+        assert!(violations(schema.clone(), doc! { "a": 1.5 }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": 1 }),
+            ["a: expected bsonType long or double but found int"]
+        );
+    }
+
+    #[test]
+    fn accepts_every_numeric_type_as_a_number() {
+        let schema = doc! { "properties": { "a": { "bsonType": "number" } } };
+        assert!(violations(schema.clone(), doc! { "a": 1 }).is_empty());
+        assert!(violations(schema.clone(), doc! { "a": 1_i64 }).is_empty());
+        assert!(violations(schema.clone(), doc! { "a": 1.5 }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": "1" }),
+            ["a: expected bsonType number but found string"]
+        );
+    }
+
+    #[test]
+    fn compares_decimals_by_value() {
+        assert_eq!(as_number(&decimal(2, 0)), Some(2.0));
+        assert_eq!(as_number(&decimal(-15, -1)), Some(-1.5));
+        assert_eq!(as_number(&decimal(12, 3)), Some(12000.0));
+
+        let schema = doc! { "properties": { "a": { "enum": [1.5] } } };
+        assert!(violations(schema.clone(), doc! { "a": decimal(15, -1) }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": decimal(2, 0) }).len(),
+            1,
+            "NumberDecimal(\"2\") is not 1.5"
+        );
+
+        let schema = doc! { "properties": { "a": { "uniqueItems": true } } };
+        assert!(violations(schema, doc! { "a": [decimal(1, 0), decimal(2, 0)] }).is_empty());
+
+        let schema = doc! { "properties": { "a": { "maximum": 10 } } };
+        assert_eq!(
+            violations(schema, doc! { "a": decimal(11, 0) }),
+            ["a: 11 is greater than the maximum 10"]
+        );
+    }
+
+    #[test]
+    fn reports_missing_required_fields() {
+        let schema = doc! { "required": ["a", "b"] };
+        assert!(violations(schema.clone(), doc! { "a": 1, "b": 2 }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": 1 }),
+            ["b: required field is missing"]
+        );
+    }
+
+    #[test]
+    fn checks_the_minimum_and_maximum() {
+        let schema = doc! { "properties": { "a": { "minimum": 1, "maximum": 10 } } };
+        assert!(violations(schema.clone(), doc! { "a": 1 }).is_empty());
+        assert!(violations(schema.clone(), doc! { "a": 10.0 }).is_empty());
+        assert_eq!(
+            violations(schema.clone(), doc! { "a": 0 }),
+            ["a: 0 is less than the minimum 1"]
+        );
+        assert_eq!(
+            violations(schema.clone(), doc! { "a": 11_i64 }),
+            ["a: 11 is greater than the maximum 10"]
+        );
+        // Only numbers are compared.
+        assert!(violations(schema, doc! { "a": "0" }).is_empty());
+
+        let schema = doc! {
+            "properties": {
+                "a": {
+                    "minimum": 1,
+                    "exclusiveMinimum": true,
+                    "maximum": 10,
+                    "exclusiveMaximum": true,
+                },
+            },
+        };
+        assert_eq!(
+            violations(schema.clone(), doc! { "a": 1 }),
+            ["a: 1 is not greater than 1"]
+        );
+        assert_eq!(
+            violations(schema, doc! { "a": 10 }),
+            ["a: 10 is not less than 10"]
+        );
+    }
+
+    #[test]
+    fn checks_the_length_of_strings() {
+        let schema = doc! { "properties": { "a": { "minLength": 2, "maxLength": 3 } } };
+        assert!(violations(schema.clone(), doc! { "a": "ab" }).is_empty());
+        // The length is counted in characters, not bytes.
+        assert!(violations(schema.clone(), doc! { "a": "äöü" }).is_empty());
+        assert_eq!(
+            violations(schema.clone(), doc! { "a": "a" }),
+            ["a: length 1 is shorter than the minimum 2"]
+        );
+        assert_eq!(
+            violations(schema, doc! { "a": "abcd" }),
+            ["a: length 4 is longer than the maximum 3"]
+        );
+    }
+
+    #[test]
+    fn matches_strings_against_the_pattern() {
+        let schema = doc! { "properties": { "a": { "pattern": "^[a-z]+$" } } };
+        assert!(violations(schema.clone(), doc! { "a": "abc" }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": "a1" }),
+            ["a: 'a1' does not match the pattern '^[a-z]+$'"]
+        );
+
+        let schema = doc! { "properties": { "a": { "pattern": "(" } } };
+        assert!(violations(schema, doc! { "a": "a" })[0].starts_with("a: invalid pattern '('"));
+    }
+
+    #[test]
+    fn compares_enum_values_by_value() {
+        let schema = doc! { "properties": { "a": { "enum": [1, "b"] } } };
+        assert!(violations(schema.clone(), doc! { "a": 1.0 }).is_empty());
+        assert!(violations(schema.clone(), doc! { "a": "b" }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": "c" }),
+            [r#"a: "c" is not one of the allowed values [1, "b"]"#]
+        );
+    }
+
+    #[test]
+    fn combines_schemas() {
+        let schema = doc! {
+            "properties": {
+                "a": { "anyOf": [{ "bsonType": "string" }, { "minimum": 5 }] },
+            },
+        };
+        assert!(violations(schema.clone(), doc! { "a": "x" }).is_empty());
+        assert!(violations(schema.clone(), doc! { "a": 5 }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": 1 }),
+            ["a: does not match any of the schemas in anyOf"]
+        );
+
+        let schema = doc! {
+            "properties": {
+                "a": { "oneOf": [{ "bsonType": "number" }, { "minimum": 5 }] },
+            },
+        };
+        assert!(violations(schema.clone(), doc! { "a": "x" }).is_empty());
+        assert_eq!(
+            violations(schema.clone(), doc! { "a": 5 }),
+            ["a: matches 2 of the schemas in oneOf instead of exactly one"]
+        );
+        assert!(violations(schema, doc! { "a": 1 }).is_empty());
+
+        let schema = doc! { "properties": { "a": { "not": { "bsonType": "string" } } } };
+        assert!(violations(schema.clone(), doc! { "a": 1 }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": "x" }),
+            ["a: matches the schema in not"]
+        );
+    }
+
+    #[test]
+    fn validates_the_items_of_arrays() {
+        let schema = doc! { "properties": { "a": { "items": { "bsonType": "int" } } } };
+        assert!(violations(schema.clone(), doc! { "a": [1, 2] }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": [1, "2"] }),
+            ["a.1: expected bsonType int but found string"]
+        );
+
+        let schema = doc! {
+            "properties": {
+                "a": {
+                    "items": [{ "bsonType": "string" }, { "bsonType": "int" }],
+                    "additionalItems": false,
+                },
+            },
+        };
+        assert!(violations(schema.clone(), doc! { "a": ["x", 1] }).is_empty());
+        assert_eq!(
+            violations(schema.clone(), doc! { "a": [1, 1] }),
+            ["a.0: expected bsonType string but found int"]
+        );
+        assert_eq!(
+            violations(schema, doc! { "a": ["x", 1, true] }),
+            ["a.2: additional item is not allowed"]
+        );
+
+        let schema = doc! {
+            "properties": {
+                "a": { "items": [{ "bsonType": "string" }], "additionalItems": { "bsonType": "int" } },
+            },
+        };
+        assert!(violations(schema.clone(), doc! { "a": ["x", 1, 2] }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": ["x", "y"] }),
+            ["a.1: expected bsonType int but found string"]
+        );
+    }
+
+    #[test]
+    fn checks_additional_properties() {
+        let schema = doc! {
+            "properties": { "a": {} },
+            "patternProperties": { "^x_": {} },
+            "additionalProperties": false,
+        };
+        assert!(violations(schema.clone(), doc! { "a": 1, "x_b": 2 }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": 1, "b": 2 }),
+            ["b: additional field is not allowed"]
+        );
+
+        let schema = doc! {
+            "properties": { "a": {} },
+            "additionalProperties": { "bsonType": "string" },
+        };
+        assert!(violations(schema.clone(), doc! { "a": 1, "b": "x" }).is_empty());
+        assert_eq!(
+            violations(schema, doc! { "a": 1, "b": 2 }),
+            ["b: expected bsonType string but found int"]
+        );
+    }
+
+    #[test]
+    fn reports_the_path_of_nested_violations() {
+        let schema = doc! {
+            "bsonType": "object",
+            "required": ["address"],
+            "properties": {
+                "address": {
+                    "bsonType": "object",
+                    "required": ["city"],
+                    "properties": {
+                        "zip": { "bsonType": "string" },
+                        "lines": { "items": { "maxLength": 3 } },
+                    },
+                },
+            },
+        };
+        assert_eq!(
+            violations(
+                schema.clone(),
+                doc! { "address": { "zip": 1, "lines": ["abc", "abcd"] } }
+            ),
+            [
+                "address.city: required field is missing",
+                "address.zip: expected bsonType string but found int",
+                "address.lines.1: length 4 is longer than the maximum 3",
+            ]
+        );
+        assert_eq!(
+            violations(schema, doc! {}),
+            ["address: required field is missing"]
+        );
+        assert_eq!(
+            violations(doc! { "maxProperties": 0 }, doc! { "a": 1 }),
+            ["(document): 1 field is more than the maximum 0"]
+        );
+    }
+}
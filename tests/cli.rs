@@ -0,0 +1,831 @@
+//! Every subcommand run end to end through the binary, except `browse` which needs a terminal.
+
+mod common;
+
+use std::io::BufRead;
+
+use common::{stderr, stdout, stdout_with_input};
+use mongodb::bson::doc;
+
+fn seed(database: &common::TestDatabase) {
+    database.insert(
+        "items",
+        vec![
+            doc! { "_id": 1, "name": "apple", "price": 3 },
+            doc! { "_id": 2, "name": "bread", "price": 2 },
+        ],
+    );
+}
+
+#[test]
+fn find_one_parses_the_filter_as_json() {
+    let database = test_database!("find_one");
+    seed(&database);
+    assert_eq!(
+        stdout(&mut database.magg(&["find-one", "--input-filter", r#"{"name": "bread"}"#])),
+        "{ \"_id\": 2, \"name\": \"bread\", \"price\": 2 }\n"
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "--output-format",
+            "json",
+            "find-one",
+            "--input-filter",
+            r#"{"price": {"$gt": 2}}"#,
+            "--project",
+            r#"{"_id": 0, "name": 1}"#,
+        ])),
+        "{\"name\":\"apple\"}\n"
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&["find-one", "--input-filter", r#"{"name": "cherry"}"#])),
+        "No such documents\n"
+    );
+}
+
+#[test]
+fn find_many_filters_sorts_and_limits() {
+    let database = test_database!("find_many");
+    seed(&database);
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "--output-format",
+            "json",
+            "find-many",
+            "--input-filter",
+            r#"{"price": {"$gte": 2}}"#,
+            "--sort",
+            r#"{"price": 1}"#,
+        ])),
+        "{\"_id\":2,\"name\":\"bread\",\"price\":2}\n{\"_id\":1,\"name\":\"apple\",\"price\":3}\n"
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "find-many",
+            "--sort",
+            r#"{"price": -1}"#,
+            "--limit",
+            "1",
+            "--project",
+            r#"{"name": 1}"#,
+        ])),
+        "{ \"_id\": 1, \"name\": \"apple\" }\n"
+    );
+}
+
+#[test]
+fn count_parses_the_filter_as_json() {
+    let database = test_database!("count");
+    seed(&database);
+    assert_eq!(stdout(&mut database.magg(&["count"])), "2\n");
+    assert_eq!(
+        stdout(&mut database.magg(&["count", "--input-filter", r#"{"price": 2}"#])),
+        "1\n"
+    );
+    assert!(
+        stderr(&mut database.magg(&["count", "--input-filter", "[1]"]))
+            .contains("invalid value '[1]' for '--input-filter <INPUT_FILTER>': must be an object")
+    );
+}
+
+#[test]
+fn create_reads_arguments_files_and_stdin() {
+    let database = test_database!("create");
+    assert_eq!(
+        stdout(&mut database.magg(&["create", "--input-documents", r#"{"_id": "a", "n": 1}"#])),
+        "Successfully inserted one document with _id:\"a\"\n\n"
+    );
+    assert_eq!(
+        stdout_with_input(
+            &mut database.magg(&["create"]),
+            "{\"_id\": \"b\"}\n[{\"_id\": \"c\"}, {\"_id\": \"d\"}]\n"
+        ),
+        "Successfully inserted 3 documents with _id:\n\"b\"\n\"c\"\n\"d\"\n"
+    );
+    let file = database.write_file("documents.json", "{\"_id\": \"e\", \"tags\": [true]}\n");
+    assert_eq!(
+        stdout(&mut database.magg(&["create", "--input-file", &file])),
+        "Successfully inserted one document with _id:\"e\"\n\n"
+    );
+    assert_eq!(
+        database.documents("items"),
+        vec![
+            doc! { "_id": "a", "n": 1.0 },
+            doc! { "_id": "b" },
+            doc! { "_id": "c" },
+            doc! { "_id": "d" },
+            doc! { "_id": "e", "tags": [true] },
+        ]
+    );
+}
+
+#[test]
+fn create_validates_against_a_schema() {
+    let database = test_database!("create_schema");
+    let schema = database.write_file("schema.json", r#"{"required": ["name"]}"#);
+    let documents = "{\"_id\": 1, \"name\": \"apple\"}\n{\"_id\": 2}\n";
+    let error = stderr(&mut database.magg(&[
+        "create",
+        "--schema",
+        &schema,
+        "--input-documents",
+        documents,
+    ]));
+    assert!(error.contains("1 of 2 documents failed validation. Nothing was inserted"));
+    assert!(database.documents("items").is_empty());
+
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "create",
+            "--schema",
+            &schema,
+            "--skip-invalid",
+            "--input-documents",
+            documents,
+        ])),
+        "Document 1: name: required field is missing\n\
+        Skipped 1 invalid document, written to rejected.json\n\
+        Successfully inserted one document with _id:1\n\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(database.dir.join("rejected.json")).unwrap(),
+        "{\"_id\":2}\n"
+    );
+}
+
+#[test]
+fn delete_one_and_delete_many() {
+    let database = test_database!("delete");
+    seed(&database);
+    database.insert("items", vec![doc! { "_id": 3, "price": 2 }]);
+    assert_eq!(
+        stdout(&mut database.magg(&["delete-one", "--input-filter", r#"{"price": 2}"#])),
+        "Deleted 1 document\n"
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&["delete-many", "--input-filter", r#"{"price": {"$lt": 5}}"#])),
+        "Deleted 2 documents\n"
+    );
+    assert!(database.documents("items").is_empty());
+}
+
+#[test]
+fn list_databases_includes_the_test_database() {
+    let database = test_database!("list_databases");
+    seed(&database);
+    let table = stdout(&mut database.magg(&["list-databases"]));
+    assert!(table.starts_with("+"));
+    assert!(table.contains(&format!("| {} ", database.name)));
+    let output = stdout(&mut database.magg(&["--output-format", "json", "list-databases"]));
+    let listed = output
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|listed| listed["name"] == database.name.as_str())
+        .expect("The test database is listed");
+    assert!(listed["sizeOnDisk"].is_number());
+    assert_eq!(listed["empty"], false);
+}
+
+#[test]
+fn server_status_stats_and_db_stats() {
+    let database = test_database!("stats_commands");
+    seed(&database);
+    let json = |args: &[&str]| {
+        let mut command = database.magg(&["--output-format", "json"]);
+        command.args(args);
+        serde_json::from_str::<serde_json::Value>(&stdout(&mut command)).unwrap()
+    };
+
+    let status = json(&["server-status"]);
+    assert!(status["version"].is_string());
+    assert!(status["connections"]["current"].is_number());
+    assert!(status["opcounters"]["insert"].is_number());
+    let table = stdout(&mut database.magg(&["server-status"]));
+    assert!(table.contains("| Version "));
+    assert!(table.contains("| Resident memory "));
+
+    let stats = json(&["stats"]);
+    assert_eq!(stats["ns"], format!("{}.items", database.name));
+    assert_eq!(stats["count"], 2);
+    assert_eq!(stats["nindexes"], 1);
+    assert!(stats["indexSizes"]["_id_"].is_number());
+    let table = stdout(&mut database.magg(&["stats"]));
+    assert!(table.contains("| Documents             | 2 "));
+    assert!(table.contains("| Index _id_ "));
+
+    let db_stats = json(&["db-stats"]);
+    assert_eq!(db_stats["db"], database.name.as_str());
+    assert_eq!(db_stats["collections"], 1);
+    assert_eq!(db_stats["objects"], 2);
+    let table = stdout(&mut database.magg(&["db-stats"]));
+    assert!(table.contains("| Documents "));
+    assert!(table.contains(" KiB "));
+}
+
+#[test]
+fn profiler_groups_the_queries_by_shape() {
+    let database = test_database!("profiler");
+    seed(&database);
+    assert!(
+        stdout(&mut database.magg(&["profiler", "set", "--level", "2", "--slow-ms", "50"]))
+            .contains("Profiling level: 2\nSlow operation threshold: 50 ms\n")
+    );
+    for price in ["2", "3"] {
+        let filter = format!(r#"{{"price": {}}}"#, price);
+        stdout(&mut database.magg(&["find-many", "--input-filter", &filter]));
+    }
+    stdout(&mut database.magg(&["profiler", "set", "--level", "0"]));
+
+    let groups = stdout(&mut database.magg(&[
+        "--output-format",
+        "json",
+        "profiler",
+        "slowest",
+        "--namespace",
+        "*.items",
+    ]))
+    .lines()
+    .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+    .collect::<Vec<_>>();
+    let query = groups
+        .iter()
+        .find(|group| group["op"] == "query")
+        .expect("The queries were not profiled");
+    assert_eq!(query["count"], 2);
+    assert_eq!(
+        query["shape"]["filter"],
+        serde_json::json!({ "price": "?" })
+    );
+}
+
+#[test]
+fn current_op_lists_the_running_operations() {
+    let database = test_database!("current_op");
+    let all = stdout(&mut database.magg(&["--output-format", "json", "current-op", "--all"]));
+    assert!(all.lines().all(|line| line.contains("\"opid\":")));
+    assert!(!all.contains("\"currentOp\":"));
+    assert_eq!(
+        stdout(&mut database.magg(&["current-op", "--min-duration", "3600"])),
+        "No operations are running\n"
+    );
+    let message = stderr(&mut database.magg(&["kill-op", "--yes", "2147483000"]));
+    assert!(message.contains("There is no operation with opid 2147483000"));
+}
+
+#[test]
+fn aggregate_runs_pipelines_and_reports_out() {
+    let database = test_database!("aggregate");
+    seed(&database);
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "--output-format",
+            "json",
+            "aggregate",
+            "--pipeline",
+            r#"[{"$group": {"_id": null, "total": {"$sum": "$price"}}}]"#,
+        ])),
+        "{\"_id\":null,\"total\":5}\n"
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "aggregate",
+            "--pipeline",
+            r#"[{"$match": {"price": 3}}, {"$out": "expensive"}]"#,
+        ])),
+        format!(
+            "Wrote the result to {}.expensive, which now has 1 document\n",
+            database.name
+        )
+    );
+    assert_eq!(
+        database.documents("expensive"),
+        vec![doc! { "_id": 1, "name": "apple", "price": 3 }]
+    );
+}
+
+fn write_config(database: &common::TestDatabase) -> String {
+    let config = serde_json::json!({
+        "connection_uri": database.uri,
+        "database_name": database.name,
+        "collection_name": "items",
+        "pipelines": [
+            {
+                "name": "names",
+                "description": "The names, sorted",
+                "stages": [{ "$sort": { "name": 1 } }, { "$project": { "_id": 0, "name": 1 } }],
+            },
+            {
+                "name": "cheap",
+                "description": ["The cheap items", "using a fragment"],
+                "stages": [{ "$include": "cheap" }],
+            },
+        ],
+        "queries": [
+            {
+                "name": "by-price",
+                "description": "Most expensive first",
+                "filter": {},
+                "sort": { "price": -1 },
+            },
+        ],
+        "fragments": {
+            "cheap": [{ "$match": { "price": { "$lt": 3 } } }],
+        },
+    });
+    database.write_file(
+        "config.json",
+        &serde_json::to_string_pretty(&config).unwrap(),
+    )
+}
+
+#[test]
+fn config_file_pipelines_and_queries() {
+    let database = test_database!("config_file");
+    seed(&database);
+    let config = write_config(&database);
+    let magg = |args: &[&str]| {
+        let mut command = database.command();
+        command.args(["--config-file", &config]).args(args);
+        stdout(&mut command)
+    };
+    assert_eq!(
+        magg(&[
+            "--output-format",
+            "json",
+            "aggregate",
+            "--pipeline-index",
+            "0"
+        ]),
+        "{\"name\":\"apple\"}\n{\"name\":\"bread\"}\n"
+    );
+    assert_eq!(
+        magg(&[
+            "--output-format",
+            "json",
+            "aggregate",
+            "--pipeline-index",
+            "1"
+        ]),
+        "{\"_id\":2,\"name\":\"bread\",\"price\":2}\n"
+    );
+    assert_eq!(
+        magg(&["aggregate", "--pipeline-name", "cheap"]),
+        "{ \"_id\": 2, \"name\": \"bread\", \"price\": 2 }\n"
+    );
+    let list = magg(&["aggregate", "--list"]);
+    assert!(list.contains("| 0     | names | The names, sorted | items   |"));
+    assert!(list.contains("| 1     | cheap | The cheap items   | items   |"));
+    assert_eq!(
+        magg(&[
+            "--output-format",
+            "json",
+            "find-many",
+            "--query-name",
+            "by-price"
+        ]),
+        "{\"_id\":1,\"name\":\"apple\",\"price\":3}\n{\"_id\":2,\"name\":\"bread\",\"price\":2}\n"
+    );
+}
+
+#[test]
+fn aggregate_saves_pipelines_to_the_config_file() {
+    let database = test_database!("save_as");
+    seed(&database);
+    let config = write_config(&database);
+    let mut command = database.command();
+    command.args(["--config-file", &config]).args([
+        "aggregate",
+        "--pipeline",
+        r#"[{"$count": "total"}]"#,
+        "--save-as",
+        "total",
+    ]);
+    assert_eq!(
+        stdout(&mut command),
+        "{ \"total\": 2 }\nSaved the pipeline as 'total'\n"
+    );
+    let saved =
+        serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(&config).unwrap())
+            .unwrap();
+    assert_eq!(saved["pipelines"][2]["name"], "total");
+    let mut command = database.command();
+    command
+        .args(["--config-file", &config])
+        .args(["aggregate", "--pipeline-index", "2"]);
+    assert_eq!(stdout(&mut command), "{ \"total\": 2 }\n");
+}
+
+#[test]
+fn bulk_write_summarizes_the_operations() {
+    let database = test_database!("bulk_write");
+    seed(&database);
+    let operations = [
+        r#"{"insertOne": {"document": {"_id": 3, "name": "cherry"}}}"#,
+        r#"{"updateOne": {"filter": {"_id": 1}, "update": {"$set": {"price": 4}}}}"#,
+        r#"{"updateOne": {"filter": {"_id": 4}, "update": {"$set": {"price": 1}}, "upsert": true}}"#,
+        r#"{"deleteMany": {"filter": {"price": 2}}}"#,
+    ]
+    .join("\n");
+    assert_eq!(
+        stdout(&mut database.magg(&["bulk-write", "--input-operations", &operations])),
+        "Inserted: 1\nMatched: 1\nModified: 1\nDeleted: 1\nUpserted: 1\n"
+    );
+    assert_eq!(database.documents("items").len(), 3);
+}
+
+#[test]
+fn transaction_commits_or_rolls_back() {
+    let database = test_database!("transaction");
+    seed(&database);
+    let operations = [
+        r#"{"create": {"_id": 3, "name": "cherry"}}"#,
+        r#"{"collection": "log", "create": [{"_id": 1}, {"_id": 2}]}"#,
+        r#"{"delete": {"filter": {"_id": 1}}}"#,
+    ]
+    .join("\n");
+    assert_eq!(
+        stdout(&mut database.magg(&["transaction", "--input-operations", &operations])),
+        "Line 1: inserted 1 document into 'items'\n\
+        Line 2: inserted 2 documents into 'log'\n\
+        Line 3: deleted 1 document from 'items'\n\
+        Committed 3 operations\n"
+    );
+
+    let operations = [r#"{"create": {"_id": 4}}"#, r#"{"create": {"_id": 2}}"#].join("\n");
+    let output = database
+        .magg(&["transaction", "--input-operations", &operations])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Transaction failed at line 2: "));
+    assert!(stdout.ends_with("The transaction was rolled back. No changes were applied\n"));
+    assert_eq!(
+        database.documents("items"),
+        vec![
+            doc! { "_id": 2, "name": "bread", "price": 2 },
+            doc! { "_id": 3.0, "name": "cherry" },
+        ]
+    );
+}
+
+#[test]
+fn watch_prints_the_change_events() {
+    let database = test_database!("watch");
+    let admin = database.client.database("admin");
+    let operation_time = admin
+        .run_command(doc! { "ping": 1 }, None)
+        .unwrap()
+        .get_timestamp("operationTime")
+        .unwrap();
+    seed(&database);
+    let mut child = database
+        .magg(&[
+            "--output-format",
+            "json",
+            "watch",
+            "--start-at-operation-time",
+            &format!("{}:{}", operation_time.time, operation_time.increment),
+        ])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = std::io::BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut events = vec![];
+    for _ in 0..2 {
+        let line = lines.next().unwrap().unwrap();
+        events.push(serde_json::from_str::<serde_json::Value>(&line).unwrap());
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(events[0]["operationType"], "insert");
+    assert_eq!(
+        events[0]["fullDocument"],
+        serde_json::json!({ "_id": 1, "name": "apple", "price": 3 })
+    );
+    assert_eq!(events[1]["documentKey"], serde_json::json!({ "_id": 2 }));
+}
+
+#[test]
+fn dump_and_restore_a_collection() {
+    let database = test_database!("dump");
+    seed(&database);
+    database
+        .collection("items")
+        .create_index(
+            mongodb::IndexModel::builder()
+                .keys(doc! { "name": 1 })
+                .build(),
+            None,
+        )
+        .unwrap();
+    assert_eq!(
+        stdout(&mut database.magg(&["dump", "--input-filter", r#"{"price": 3}"#])),
+        format!(
+            "Dumped 1 document from {0}.items to dump/{0}/items.bson\n",
+            database.name
+        )
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "restore",
+            "--ns-from",
+            &format!("{}.*", database.name),
+            "--ns-to",
+            &format!("{}.restored_*", database.name),
+        ])),
+        format!(
+            "Restored 1 document and 1 index into {0}.restored_items from dump/{0}/items.bson\n",
+            database.name
+        )
+    );
+    assert_eq!(
+        database.documents("restored_items"),
+        vec![doc! { "_id": 1, "name": "apple", "price": 3 }]
+    );
+}
+
+#[test]
+fn dump_of_a_missing_collection_writes_nothing() {
+    let database = test_database!("dump_missing");
+    let error = stderr(&mut database.magg_on("missing", &["dump"]));
+    assert!(error.contains(&format!(
+        "The collection {}.missing does not exist",
+        database.name
+    )));
+    assert!(!database.dir.join("dump").exists());
+}
+
+#[test]
+fn copy_to_another_collection() {
+    let database = test_database!("copy");
+    seed(&database);
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "copy",
+            "--input-filter",
+            r#"{"price": 2}"#,
+            "--target-collection-name",
+            "copied",
+        ])),
+        format!(
+            "Copied 1 document from {0}.items to {0}.copied\n",
+            database.name
+        )
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&[
+            "copy",
+            "--pipeline",
+            r#"[{"$set": {"copied": true}}]"#,
+            "--target-collection-name",
+            "copied",
+            "--upsert-by",
+            "_id",
+        ])),
+        format!(
+            "Copied 2 documents from {0}.items to {0}.copied\n",
+            database.name
+        )
+    );
+    assert_eq!(
+        database.documents("copied"),
+        vec![
+            doc! { "_id": 1, "name": "apple", "price": 3, "copied": true },
+            doc! { "_id": 2, "name": "bread", "price": 2, "copied": true },
+        ]
+    );
+}
+
+#[test]
+fn schema_describes_the_documents() {
+    let database = test_database!("schema");
+    seed(&database);
+    let output = stdout(&mut database.magg(&["schema", "--json-schema"]));
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&output).unwrap(),
+        serde_json::json!({
+            "$jsonSchema": {
+                "bsonType": "object",
+                "required": ["_id", "name", "price"],
+                "properties": {
+                    "_id": { "bsonType": "int" },
+                    "name": { "bsonType": "string" },
+                    "price": { "bsonType": "int" },
+                },
+            },
+        })
+    );
+    let table = stdout(&mut database.magg(&["schema"]));
+    assert!(table.ends_with("Analyzed 2 documents\n"));
+}
+
+#[test]
+fn validator_set_show_and_check() {
+    let database = test_database!("validator");
+    seed(&database);
+    database.insert("items", vec![doc! { "_id": 3 }]);
+    let validator = database.write_file(
+        "validator.json",
+        r#"{"$jsonSchema": {"required": ["name"]}}"#,
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&["validator", "set", "--input-file", &validator])),
+        format!("Updated the validator of {}.items\n", database.name)
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&["validator", "show"])),
+        "{\n  \"$jsonSchema\": {\n    \"required\": [\n      \"name\"\n    ]\n  }\n}\n\
+        Validation level: strict\n\
+        Validation action: error\n"
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&["validator", "check"])),
+        "_id: 3\n  name: required field is missing\n1 of 3 documents would fail validation\n"
+    );
+}
+
+#[test]
+fn shell_runs_commands_from_stdin() {
+    let database = test_database!("shell");
+    seed(&database);
+    database.insert("other", vec![doc! { "_id": 1 }]);
+    assert_eq!(
+        stdout_with_input(
+            &mut database.magg(&["shell"]),
+            "count\ncoll other\nfind-one\ncount --input-filter {}\nexit\ncount\n"
+        ),
+        "2\nSwitched to collection other\n{ \"_id\": 1 }\n1\n"
+    );
+}
+
+#[test]
+fn browse_needs_a_terminal() {
+    let database = test_database!("browse");
+    assert!(stderr(&mut database.magg(&["browse"]))
+        .contains("The browser needs an interactive terminal"));
+}
+
+#[test]
+fn edit_replaces_the_document() {
+    let database = test_database!("edit");
+    seed(&database);
+    let mut command = database.magg(&["edit", "--input-filter", r#"{"_id": 1}"#, "--yes"]);
+    command.env("EDITOR", "sed -i s/apple/pear/");
+    assert_eq!(
+        stdout(&mut command),
+        "~ name: \"apple\" -> \"pear\"\nReplaced the document (1 field changed)\n"
+    );
+    // The unchanged fields keep their type.
+    assert_eq!(
+        database.documents("items")[0],
+        doc! { "_id": 1, "name": "pear", "price": 3 }
+    );
+}
+
+#[test]
+fn edit_selects_the_document_by_object_id() {
+    let database = test_database!("edit_object_id");
+    let id = mongodb::bson::oid::ObjectId::new();
+    database.insert("items", vec![doc! { "_id": id, "name": "apple" }]);
+    let filter = format!(r#"{{"_id": {{"$oid": "{}"}}}}"#, id.to_hex());
+    let mut command = database.magg(&["edit", "--input-filter", &filter, "--yes"]);
+    command.env("EDITOR", "sed -i s/apple/pear/");
+    assert_eq!(
+        stdout(&mut command),
+        "~ name: \"apple\" -> \"pear\"\nReplaced the document (1 field changed)\n"
+    );
+    assert_eq!(
+        database.documents("items"),
+        vec![doc! { "_id": id, "name": "pear" }]
+    );
+}
+
+#[test]
+fn history_lists_and_reruns_commands() {
+    let database = test_database!("history");
+    seed(&database);
+    stdout(&mut database.magg(&["count"]));
+    stdout(&mut database.magg(&["count", "--input-filter", r#"{"price": 2}"#]));
+    let history = stdout(&mut database.magg(&["history", "--search", "input-filter"]));
+    let lines = history.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with("    2  "));
+    assert!(lines[0].ends_with(r#"count --input-filter '{"price": 2}'"#));
+    // Listing needs no connection.
+    assert_eq!(
+        stdout(database.command().args(["history", "--limit", "1"])),
+        history
+    );
+    assert_eq!(
+        stdout(&mut database.magg(&["history", "--rerun", "1"])),
+        "2\n"
+    );
+}
+
+#[test]
+fn shell_does_not_record_the_history_subcommand() {
+    let database = test_database!("shell_rerun");
+    seed(&database);
+    assert_eq!(
+        stdout_with_input(
+            &mut database.magg(&["shell"]),
+            "count\nhistory --rerun 1\nhistory\nexit\n"
+        )
+        .lines()
+        .take(2)
+        .collect::<Vec<_>>(),
+        ["2", "2"]
+    );
+    // The count, its re-run and the shell itself.
+    let history = stdout(database.command().arg("history"));
+    assert_eq!(history.lines().count(), 3, "{}", history);
+    assert!(!history.contains(" history"), "{}", history);
+}
+
+#[test]
+fn verbose_logs_the_commands_to_stderr() {
+    let database = test_database!("verbose");
+    seed(&database);
+    let output = database
+        .magg(&["count", "-vv", "--log-format", "json", "--redact"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    let logs = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let started = logs
+        .iter()
+        .find(|log| log["message"] == "command started" && log["command"] == "aggregate")
+        .expect("The count is logged");
+    assert_eq!(started["database"], database.name.as_str());
+    assert_eq!(started["body"]["aggregate"], "?");
+    let succeeded = logs
+        .iter()
+        .find(|log| log["message"] == "command succeeded" && log["command"] == "aggregate")
+        .expect("The reply is logged");
+    assert_eq!(succeeded["status"], "ok");
+    assert!(succeeded["duration_ms"].is_f64());
+    assert_eq!(succeeded["reply"]["ok"], "?");
+}
+
+#[test]
+fn stats_summarize_the_commands() {
+    let database = test_database!("stats");
+    seed(&database);
+    let output = database.magg(&["find-many", "--stats"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 2);
+    let stats = String::from_utf8_lossy(&output.stderr);
+    assert!(stats.starts_with("Wall time: "));
+    assert!(stats.contains("\n  Connect: "));
+    assert!(stats.contains("\n  Server: "));
+    assert!(stats.contains("\n  Output: "));
+    assert!(stats.contains("\nDocuments returned: 2\nDocuments affected: 0\n"));
+    assert!(stats.ends_with("Commands: 1\ngetMore round trips: 0\n"));
+
+    let output = database
+        .magg(&["--stats", "delete-many", "--input-filter", "{}"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\nDocuments affected: 2\n"));
+}
+
+#[test]
+fn completions_complete_the_saved_pipelines_and_the_names() {
+    let database = test_database!("completions");
+    seed(&database);
+    let script = stdout(database.command().args(["completions", "bash"]));
+    assert!(script.contains("$(_magg_values pipeline-names)"));
+    assert!(stdout(database.command().arg("man")).starts_with(".TH MAGG 1 "));
+
+    let config = database.write_file(
+        "config.json",
+        &serde_json::json!({
+            "connection_uri": database.uri,
+            "database_name": database.name,
+            "collection_name": "items",
+            "pipelines": [
+                { "name": "cheapest", "description": "", "stages": [] },
+                { "name": "total", "description": "", "stages": [] },
+            ],
+        })
+        .to_string(),
+    );
+    let complete = |kind: &str| {
+        stdout(
+            database
+                .command()
+                .args(["--config-file", &config, "complete", kind]),
+        )
+    };
+    assert_eq!(complete("pipeline-names"), "cheapest\ntotal\n");
+    assert_eq!(complete("pipeline-indexes"), "0\n1\n");
+    assert_eq!(complete("collections"), "items\n");
+    assert!(complete("databases")
+        .lines()
+        .any(|name| name == database.name));
+}
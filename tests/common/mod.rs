@@ -0,0 +1,252 @@
+//! Runs the `magg` binary against a throwaway `mongod`.
+//!
+//! The server is started once per test binary as a single node replica set, so transactions and
+//! change streams work, and every test gets a database of its own. The tests are skipped when
+//! `mongod` is not installed. Set `MONGOD` to use a binary that is not on the `PATH`.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+const REPLICA_SET: &str = "magg";
+
+/// Stops the server and removes its data once the test binary exits and closes its stdin.
+const SUPERVISOR: &str = r#"
+"$3" --dbpath "$1" --port "$2" --bind_ip 127.0.0.1 --replSet magg --logpath "$1/mongod.log" &
+pid=$!
+cat > /dev/null
+kill $pid
+wait $pid
+rm -rf "$1"
+"#;
+
+pub struct Server {
+    pub uri: String,
+    // Its stdin is kept open for the lifetime of the test binary, see `SUPERVISOR`.
+    _supervisor: std::process::Child,
+}
+
+fn mongod() -> String {
+    std::env::var("MONGOD").unwrap_or_else(|_| "mongod".to_string())
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .expect("There is no free port")
+}
+
+/// Initiate the replica set and wait for the node to become primary.
+fn initiate(uri: &str, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+    let client =
+        mongodb::sync::Client::with_uri_str(format!("{}&serverSelectionTimeoutMS=500", uri))?;
+    let admin = client.database("admin");
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let mut initiated = false;
+    loop {
+        if !initiated {
+            let config = mongodb::bson::doc! {
+                "_id": REPLICA_SET,
+                "members": [{ "_id": 0, "host": format!("127.0.0.1:{}", port) }],
+            };
+            initiated = admin
+                .run_command(mongodb::bson::doc! { "replSetInitiate": config }, None)
+                .is_ok();
+        }
+        if initiated {
+            let hello = admin.run_command(mongodb::bson::doc! { "isMaster": 1 }, None)?;
+            if hello.get_bool("ismaster") == Ok(true) {
+                return Ok(());
+            }
+        }
+        if Instant::now() > deadline {
+            return Err("mongod did not become primary in time".into());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn start() -> Option<Server> {
+    let installed = Command::new(mongod())
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !installed {
+        eprintln!("mongod is not installed, skipping the integration tests");
+        return None;
+    }
+    let port = free_port();
+    let dbpath = std::env::temp_dir().join(format!("magg-mongod-{}", std::process::id()));
+    std::fs::create_dir_all(&dbpath).expect("Cannot create the data directory");
+    let supervisor = Command::new("sh")
+        .arg("-c")
+        .arg(SUPERVISOR)
+        .arg("sh")
+        .arg(&dbpath)
+        .arg(port.to_string())
+        .arg(mongod())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Cannot start mongod");
+    let uri = format!("mongodb://127.0.0.1:{}/?directConnection=true", port);
+    if let Err(e) = initiate(&uri, port) {
+        panic!(
+            "Cannot start mongod: {}. See {}",
+            e,
+            dbpath.join("mongod.log").display()
+        );
+    }
+    Some(Server {
+        uri,
+        _supervisor: supervisor,
+    })
+}
+
+/// The server shared by the tests of this binary, if `mongod` is installed.
+pub fn server() -> Option<&'static Server> {
+    static SERVER: OnceLock<Option<Server>> = OnceLock::new();
+    SERVER.get_or_init(start).as_ref()
+}
+
+/// A database for a single test, with a directory used as the working directory and the home of
+/// the binary. Both are removed when it is dropped.
+pub struct TestDatabase {
+    pub uri: String,
+    pub name: String,
+    pub dir: std::path::PathBuf,
+    pub client: mongodb::sync::Client,
+}
+
+/// The test database with the given name, or return from the test if there is no server.
+#[macro_export]
+macro_rules! test_database {
+    ($name:expr) => {
+        match common::TestDatabase::new($name) {
+            Some(database) => database,
+            None => return,
+        }
+    };
+}
+
+impl TestDatabase {
+    pub fn new(test_name: &str) -> Option<Self> {
+        let server = server()?;
+        let name = format!("magg_{}_{}", test_name, std::process::id());
+        let dir = std::env::temp_dir().join(&name);
+        std::fs::create_dir_all(&dir).expect("Cannot create the test directory");
+        let client = mongodb::sync::Client::with_uri_str(&server.uri).expect("Cannot connect");
+        Some(TestDatabase {
+            uri: server.uri.clone(),
+            name,
+            dir,
+            client,
+        })
+    }
+
+    pub fn collection(&self, name: &str) -> mongodb::sync::Collection<mongodb::bson::Document> {
+        self.client.database(&self.name).collection(name)
+    }
+
+    pub fn insert(&self, collection_name: &str, documents: Vec<mongodb::bson::Document>) {
+        self.collection(collection_name)
+            .insert_many(documents, None)
+            .expect("Cannot insert the documents");
+    }
+
+    /// The documents of a collection, sorted by _id.
+    pub fn documents(&self, collection_name: &str) -> Vec<mongodb::bson::Document> {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(mongodb::bson::doc! { "_id": 1 })
+            .build();
+        self.collection(collection_name)
+            .find(None, options)
+            .expect("Cannot find the documents")
+            .collect::<Result<_, _>>()
+            .expect("Cannot read the documents")
+    }
+
+    /// Write a file into the test directory and return its path.
+    pub fn write_file(&self, name: &str, contents: &str) -> String {
+        let path = self.dir.join(name);
+        std::fs::write(&path, contents).expect("Cannot write the file");
+        path.to_string_lossy().to_string()
+    }
+
+    /// The binary, without a connection.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_magg"));
+        command
+            .current_dir(&self.dir)
+            .env("HOME", &self.dir)
+            .stdin(Stdio::null());
+        command
+    }
+
+    /// The binary, connected to the `items` collection of the test database.
+    pub fn magg(&self, args: &[&str]) -> Command {
+        self.magg_on("items", args)
+    }
+
+    pub fn magg_on(&self, collection_name: &str, args: &[&str]) -> Command {
+        let mut command = self.command();
+        command
+            .args(["--connection-uri", &self.uri])
+            .args(["--database-name", &self.name])
+            .args(["--collection-name", collection_name])
+            .args(args);
+        command
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        let _ = self.client.database(&self.name).drop(None);
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Run the command and return its stdout, failing the test if it does not succeed.
+pub fn stdout(command: &mut Command) -> String {
+    check(command.output().expect("Cannot run magg"))
+}
+
+/// Like `stdout`, with the input piped into the command.
+pub fn stdout_with_input(command: &mut Command, input: &str) -> String {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Cannot run magg");
+    child
+        .stdin
+        .take()
+        .expect("The stdin is piped")
+        .write_all(input.as_bytes())
+        .expect("Cannot write the input");
+    check(child.wait_with_output().expect("Cannot run magg"))
+}
+
+/// Run the command and return its stderr, failing the test if it succeeds.
+pub fn stderr(command: &mut Command) -> String {
+    let output = command.output().expect("Cannot run magg");
+    assert!(
+        !output.status.success(),
+        "magg succeeded with {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+fn check(output: Output) -> String {
+    assert!(
+        output.status.success(),
+        "magg failed with {}\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).expect("The output is not UTF-8")
+}